@@ -0,0 +1,30 @@
+use aoc2018::recipes::{Nibbles, RecipeBoard, Scores};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+const NUM_RECIPES: usize = 20_000_000;
+
+/// Creates the recipes of the puzzle in the given storage, and returns the memory it takes.
+fn create_recipes<S: Scores>(storage: S) -> usize {
+    let mut recipes = RecipeBoard::default().scores_iter_in(storage).unwrap();
+    recipes.nth(NUM_RECIPES).unwrap();
+    recipes.scores().heap_size()
+}
+
+fn bench_storage(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("{} recipes", NUM_RECIPES));
+    group.sample_size(10);
+    let mut bench = |name: &str, create: fn() -> usize| {
+        eprintln!("{}: {} MiB", name, create() >> 20);
+        group.bench_function(name, |b| b.iter(create));
+    };
+    bench("Vec<usize>", || create_recipes(Vec::<usize>::new()));
+    bench("Vec<u8>", || create_recipes(Vec::<u8>::new()));
+    bench("Vec<u8> preallocated", || {
+        create_recipes(Vec::<u8>::with_capacity(NUM_RECIPES + 2))
+    });
+    bench("Nibbles", || create_recipes(Nibbles::default()));
+    group.finish();
+}
+
+criterion_group!(benches, bench_storage);
+criterion_main!(benches);
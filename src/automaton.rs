@@ -0,0 +1,773 @@
+use anyhow::Error;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+/// Lookup table mapping every neighbourhood of `2 * radius + 1` cells to the next value of the
+/// centre cell. Neighbourhoods are encoded as integers with the leftmost cell as the most
+/// significant bit.
+#[derive(Debug, Clone)]
+pub struct Rules {
+    radius: usize,
+    produce_sets: Vec<bool>,
+}
+
+/// The longest rule pattern accepted when parsing, keeping the lookup table small.
+pub const MAX_PATTERN_WIDTH: usize = 15;
+
+/// How neighbourhoods without a rule are treated when parsing a rule file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MissingRules {
+    /// The centre cell becomes empty.
+    #[default]
+    Empty,
+    Error,
+}
+
+/// Rules parsed from a rule file, together with diagnostics that do not prevent simulating them.
+#[derive(Debug)]
+pub struct ParsedRules {
+    pub rules: Rules,
+    pub warnings: Vec<String>,
+}
+
+impl Rules {
+    pub fn new(radius: usize) -> Self {
+        Self {
+            radius,
+            produce_sets: vec![false; 1 << (2 * radius + 1)],
+        }
+    }
+
+    /// An elementary (radius 1) automaton using Wolfram's rule numbering.
+    pub fn from_wolfram(number: u8) -> Self {
+        let mut rules = Self::new(1);
+        for pattern in 0..8 {
+            rules.set(pattern, (number >> pattern) & 1 == 1);
+        }
+        rules
+    }
+
+    /// Parses rules in the `..#.# => #` syntax. The radius follows from the pattern length, and
+    /// patterns without a rule produce an empty cell.
+    pub fn from_lines<I, S>(lines: I) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Ok(Self::parse(lines, 1, MissingRules::Empty)?.rules)
+    }
+
+    /// Strictly parses rules in the `..#.# => #` syntax, numbering the lines from
+    /// `first_line_number` in diagnostics. All errors are reported together.
+    pub fn parse<I, S>(
+        lines: I,
+        first_line_number: usize,
+        missing: MissingRules,
+    ) -> Result<ParsedRules, Error>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut errors = vec![];
+        let mut width: Option<(usize, usize)> = None;
+        let mut defined: HashMap<usize, (usize, bool)> = HashMap::new();
+
+        for (line_number, line) in (first_line_number..).zip(lines) {
+            let line = line.as_ref();
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some((pattern, result)) = line.split_once(" => ") else {
+                errors.push(format!(
+                    "line {}: expected `<pattern> => <result>`",
+                    line_number
+                ));
+                continue;
+            };
+            if let Some(c) = pattern
+                .chars()
+                .chain(result.chars())
+                .find(|c| *c != '#' && *c != '.')
+            {
+                errors.push(format!("line {}: invalid character '{}'", line_number, c));
+                continue;
+            }
+            if result.len() != 1 {
+                errors.push(format!(
+                    "line {}: result must be a single `#` or `.`",
+                    line_number
+                ));
+                continue;
+            }
+            match width {
+                _ if pattern.len() % 2 == 0 => {
+                    errors.push(format!(
+                        "line {}: pattern has even length {}",
+                        line_number,
+                        pattern.len()
+                    ));
+                    continue;
+                }
+                _ if pattern.len() > MAX_PATTERN_WIDTH => {
+                    errors.push(format!(
+                        "line {}: pattern too long (max {})",
+                        line_number, MAX_PATTERN_WIDTH
+                    ));
+                    continue;
+                }
+                Some((width, first_line)) if width != pattern.len() => {
+                    errors.push(format!(
+                        "line {}: pattern has length {}, expected {} as on line {}",
+                        line_number,
+                        pattern.len(),
+                        width,
+                        first_line
+                    ));
+                    continue;
+                }
+                Some(_) => {}
+                None => width = Some((pattern.len(), line_number)),
+            }
+
+            let produce = result == "#";
+            match defined.get(&parse_pattern(pattern)) {
+                Some((other_line, other_produce)) if *other_produce == produce => {
+                    errors.push(format!(
+                        "line {}: duplicate of the rule on line {}",
+                        line_number, other_line
+                    ))
+                }
+                Some((other_line, _)) => errors.push(format!(
+                    "line {}: conflicts with the rule on line {}",
+                    line_number, other_line
+                )),
+                None => {
+                    defined.insert(parse_pattern(pattern), (line_number, produce));
+                }
+            }
+        }
+
+        let Some((width, _)) = width else {
+            errors.push("no rules".to_string());
+            Err(Error::msg(errors.join("\n")))?
+        };
+        let mut rules = Self::new(width / 2);
+        for (pattern, (_, produce)) in defined.iter() {
+            rules.set(*pattern, *produce);
+        }
+
+        let missing_patterns: Vec<_> = (0..1 << width)
+            .filter(|pattern| !defined.contains_key(pattern))
+            .collect();
+        if missing == MissingRules::Error && !missing_patterns.is_empty() {
+            errors.push(format!(
+                "missing rules for {}",
+                missing_patterns
+                    .iter()
+                    .map(|p| format!("`{}`", format_pattern(*p, width)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        if !errors.is_empty() {
+            Err(Error::msg(errors.join("\n")))?
+        }
+
+        let mut warnings = vec![];
+        if let Some((line_number, true)) = defined.get(&0) {
+            warnings.push(format!(
+                "line {}: `{}` produces a plant, so the empty tape fills up",
+                line_number,
+                format_pattern(0, width)
+            ));
+        }
+        Ok(ParsedRules { rules, warnings })
+    }
+
+    pub fn radius(&self) -> usize {
+        self.radius
+    }
+
+    /// The number of cells in a neighbourhood.
+    pub fn width(&self) -> usize {
+        2 * self.radius + 1
+    }
+
+    pub fn set(&mut self, pattern: usize, produce: bool) {
+        self.produce_sets[pattern] = produce;
+    }
+
+    pub fn produce(&self, pattern: usize) -> bool {
+        self.produce_sets[pattern]
+    }
+}
+
+fn format_pattern(pattern: usize, width: usize) -> String {
+    (0..width)
+        .rev()
+        .map(|bit| if (pattern >> bit) & 1 == 1 { '#' } else { '.' })
+        .collect()
+}
+
+fn parse_pattern(pattern: &str) -> usize {
+    pattern
+        .chars()
+        .map(|c| (c == '#') as usize)
+        .fold(0, |acc, el| 2 * acc + el)
+}
+
+const WORD_BITS: usize = 64;
+
+/// A finite window on an infinite tape, stored as a bitset. `index` is the position of the first
+/// cell, and every cell outside the window holds the `background` value. The window is kept
+/// trimmed so that both its first and last cell differ from the background.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    words: Vec<u64>,
+    len: usize,
+    index: i64,
+    background: bool,
+}
+
+impl State {
+    pub fn from_string(s: &str) -> Self {
+        let mut words = vec![0; s.len().div_ceil(WORD_BITS)];
+        for (i, c) in s.chars().enumerate() {
+            if c == '#' {
+                words[i / WORD_BITS] |= 1 << (i % WORD_BITS);
+            }
+        }
+        Self {
+            words,
+            len: s.len(),
+            index: 0,
+            background: false,
+        }
+        .trimmed()
+    }
+
+    /// The cells of the window, starting at `index`.
+    pub fn cells(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|i| self.bit(i))
+    }
+
+    /// The number of cells in the window.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn index(&self) -> i64 {
+        self.index
+    }
+
+    /// Whether every pot outside the window holds a plant.
+    pub fn background(&self) -> bool {
+        self.background
+    }
+
+    /// Whether the pot at absolute position `pot` holds a plant.
+    pub fn get(&self, pot: i64) -> bool {
+        if pot >= self.index && ((pot - self.index) as usize) < self.len {
+            self.bit((pot - self.index) as usize)
+        } else {
+            self.background
+        }
+    }
+
+    pub fn sum_plant_indices(&self) -> Result<i64, Error> {
+        self.check_finite()?;
+        Ok(self
+            .words
+            .iter()
+            .enumerate()
+            .map(|(i, word)| {
+                let mut word = *word;
+                let mut sum = 0;
+                while word != 0 {
+                    sum += (i * WORD_BITS) as i64 + word.trailing_zeros() as i64 + self.index;
+                    word &= word - 1;
+                }
+                sum
+            })
+            .sum())
+    }
+
+    pub fn count_plants(&self) -> Result<i64, Error> {
+        self.check_finite()?;
+        Ok(self.words.iter().map(|w| w.count_ones() as i64).sum())
+    }
+
+    /// The position of the leftmost plant, or `None` when there are no plants at all.
+    pub fn leftmost_plant(&self) -> Result<Option<i64>, Error> {
+        self.check_finite()?;
+        Ok((!self.is_empty()).then_some(self.index))
+    }
+
+    /// The position of the rightmost plant, or `None` when there are no plants at all.
+    pub fn rightmost_plant(&self) -> Result<Option<i64>, Error> {
+        self.check_finite()?;
+        Ok((!self.is_empty()).then_some(self.index + self.len as i64 - 1))
+    }
+
+    pub fn next(&self, rules: &Rules) -> Self {
+        let width = rules.width();
+        let mask = (1 << width) - 1;
+        let background_word = if self.background { !0 } else { 0 };
+        let len = self.len + width - 1;
+        let mut words = vec![0u64; len.div_ceil(WORD_BITS)];
+        let mut window = if self.background { mask } else { 0 };
+        for (i, out) in words.iter_mut().enumerate() {
+            let word = match self.words.get(i) {
+                Some(word) if (i + 1) * WORD_BITS > self.len => {
+                    word | (background_word << (self.len % WORD_BITS))
+                }
+                Some(word) => *word,
+                None => background_word,
+            };
+            for bit in 0..WORD_BITS {
+                window = ((window << 1) | ((word >> bit) & 1) as usize) & mask;
+                if rules.produce(window) {
+                    *out |= 1 << bit;
+                }
+            }
+        }
+        State {
+            words,
+            len,
+            index: self.index - rules.radius() as i64,
+            background: rules.produce(if self.background { mask } else { 0 }),
+        }
+        .trimmed()
+    }
+
+    fn shifted(mut self, offset: i64) -> Self {
+        self.index += offset;
+        self
+    }
+
+    fn check_finite(&self) -> Result<(), Error> {
+        if self.background {
+            Err(Error::msg("Infinitely many plants"))?
+        }
+        Ok(())
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        (self.words[i / WORD_BITS] >> (i % WORD_BITS)) & 1 == 1
+    }
+
+    fn clear_unused_bits(&mut self) {
+        self.words.truncate(self.len.div_ceil(WORD_BITS));
+        if !self.len.is_multiple_of(WORD_BITS) {
+            *self.words.last_mut().unwrap() &= (1 << (self.len % WORD_BITS)) - 1;
+        }
+    }
+
+    fn invert(&mut self) {
+        for word in self.words.iter_mut() {
+            *word = !*word;
+        }
+        self.clear_unused_bits();
+    }
+
+    fn trimmed(mut self) -> Self {
+        // Trimming works on cells that differ from the background, so a full background is
+        // handled by temporarily inverting the window.
+        if self.background {
+            self.invert();
+        } else {
+            self.clear_unused_bits();
+        }
+        let (Some(first_word), Some(last_word)) = (
+            self.words.iter().position(|w| *w != 0),
+            self.words.iter().rposition(|w| *w != 0),
+        ) else {
+            return Self {
+                words: vec![],
+                len: 0,
+                index: self.index,
+                background: self.background,
+            };
+        };
+        let first = first_word * WORD_BITS + self.words[first_word].trailing_zeros() as usize;
+        let last =
+            last_word * WORD_BITS + WORD_BITS - 1 - self.words[last_word].leading_zeros() as usize;
+        let len = last + 1 - first;
+        let (skip, shift) = (first / WORD_BITS, first % WORD_BITS);
+        let words = (0..len.div_ceil(WORD_BITS))
+            .map(|i| {
+                let low = self.words[skip + i] >> shift;
+                let high = match (shift, self.words.get(skip + i + 1)) {
+                    (0, _) | (_, None) => 0,
+                    (_, Some(w)) => w << (WORD_BITS - shift),
+                };
+                low | high
+            })
+            .collect();
+        let mut result = Self {
+            words,
+            len,
+            index: self.index + first as i64,
+            background: self.background,
+        };
+        if result.background {
+            result.invert();
+        }
+        result
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for val in self.cells() {
+            write!(f, "{}", if val { '#' } else { '.' })?;
+        }
+        Ok(())
+    }
+}
+
+/// From generation `start` on, the tape repeats every `period` generations, moved `shift` cells
+/// to the right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cycle {
+    pub start: u64,
+    pub period: u64,
+    pub shift: i64,
+}
+
+/// Runs an automaton while watching for a repeating (possibly translated) state, so that results
+/// for arbitrarily late generations can be extrapolated once a cycle has been found.
+pub struct Simulation {
+    rules: Rules,
+    initial: State,
+    state: State,
+    generation: u64,
+    plants: Vec<Option<(i64, i64)>>,
+    seen: HashMap<u64, (u64, i64)>,
+    candidate: Option<(State, Cycle)>,
+    cycle: Option<Cycle>,
+}
+
+impl Simulation {
+    pub fn new(state: State, rules: Rules) -> Self {
+        let mut simulation = Self {
+            rules,
+            initial: state.clone(),
+            state,
+            generation: 0,
+            plants: vec![],
+            seen: HashMap::new(),
+            candidate: None,
+            cycle: None,
+        };
+        simulation.record();
+        simulation
+    }
+
+    pub fn cycle(&self) -> Option<Cycle> {
+        self.cycle
+    }
+
+    /// Simulates up to `generation`, stopping early when a cycle is found.
+    pub fn run_until(&mut self, generation: u64) -> Option<Cycle> {
+        while self.cycle.is_none() && self.generation < generation {
+            self.state = self.state.next(&self.rules);
+            self.generation += 1;
+            self.record();
+        }
+        self.cycle
+    }
+
+    pub fn sum_plant_indices(&mut self, generation: u64) -> Result<i64, Error> {
+        let ((sum, count), shift) = self.plants_at(generation)?;
+        Ok(sum + shift * count)
+    }
+
+    pub fn count_plants(&mut self, generation: u64) -> Result<i64, Error> {
+        Ok(self.plants_at(generation)?.0 .1)
+    }
+
+    /// The full state after `generation` generations. Earlier generations than the ones
+    /// simulated so far are recomputed from the initial state.
+    pub fn state_at(&mut self, generation: u64) -> State {
+        if generation < self.generation {
+            return (0..generation).fold(self.initial.clone(), |state, _| state.next(&self.rules));
+        }
+        match self.run_until(generation) {
+            Some(cycle) if generation > self.generation => {
+                // The current generation is one period past the start of the cycle.
+                let (k, offset) = cycle.split(generation);
+                (cycle.start..offset)
+                    .fold(self.state.clone(), |state, _| state.next(&self.rules))
+                    .shifted((k as i64 - 1) * cycle.shift)
+            }
+            _ => self.state.clone(),
+        }
+    }
+
+    /// The plant sum and count of the generation equivalent to `generation`, together with the
+    /// distance the plants have moved since.
+    fn plants_at(&mut self, generation: u64) -> Result<((i64, i64), i64), Error> {
+        let (offset, shift) = match self.run_until(generation) {
+            Some(cycle) if generation > self.generation => {
+                let (k, offset) = cycle.split(generation);
+                (offset, k as i64 * cycle.shift)
+            }
+            _ => (generation, 0),
+        };
+        match self.plants[offset as usize] {
+            Some(plants) => Ok((plants, shift)),
+            None => Err(Error::msg("Infinitely many plants")),
+        }
+    }
+
+    fn record(&mut self) {
+        self.plants.push(
+            self.state
+                .sum_plant_indices()
+                .ok()
+                .zip(self.state.count_plants().ok()),
+        );
+
+        // A hash match only suggests a cycle; it is confirmed by checking that the state
+        // reappears one period later.
+        if let Some((start_state, cycle)) = &self.candidate {
+            if self.generation == cycle.start + cycle.period {
+                if self.state.words == start_state.words
+                    && self.state.len == start_state.len
+                    && self.state.background == start_state.background
+                    && self.state.index - start_state.index == cycle.shift
+                {
+                    self.cycle = Some(*cycle);
+                }
+                self.candidate = None;
+            }
+        }
+
+        let mut hasher = DefaultHasher::new();
+        self.state.words.hash(&mut hasher);
+        self.state.background.hash(&mut hasher);
+        let previous = self
+            .seen
+            .insert(hasher.finish(), (self.generation, self.state.index()));
+        if let (Some((generation, index)), None) = (previous, &self.candidate) {
+            self.candidate = Some((
+                self.state.clone(),
+                Cycle {
+                    start: self.generation,
+                    period: self.generation - generation,
+                    shift: self.state.index() - index,
+                },
+            ));
+        }
+    }
+}
+
+impl Cycle {
+    /// Splits a generation past the start of the cycle into the number of completed periods and
+    /// the equivalent generation within the first period.
+    pub fn split(&self, generation: u64) -> (u64, u64) {
+        let since_start = generation - self.start;
+        (
+            since_start / self.period,
+            self.start + since_start % self.period,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(rules: &Rules, initial: &str, generations: usize) -> State {
+        (0..generations).fold(State::from_string(initial), |state, _| state.next(rules))
+    }
+
+    #[test]
+    fn test_wolfram_rules() {
+        let rule30 = Rules::from_wolfram(30);
+        assert_eq!(run(&rule30, "#", 1).to_string(), "###");
+        assert_eq!(run(&rule30, "#", 2).to_string(), "##..#");
+        assert_eq!(run(&rule30, "#", 2).index(), -2);
+
+        let rule90 = Rules::from_wolfram(90);
+        assert_eq!(run(&rule90, "#", 3).to_string(), "#.#.#.#");
+        assert_eq!(run(&rule90, "#", 4).to_string(), "#.......#");
+    }
+
+    #[test]
+    fn test_aoc_rules() {
+        let rules = Rules::from_lines([
+            "...## => #",
+            "..#.. => #",
+            ".#... => #",
+            ".#.#. => #",
+            ".#.## => #",
+            ".##.. => #",
+            ".#### => #",
+            "#.#.# => #",
+            "#.### => #",
+            "##.#. => #",
+            "##.## => #",
+            "###.. => #",
+            "###.# => #",
+            "####. => #",
+        ])
+        .unwrap();
+        assert_eq!(rules.radius(), 2);
+        let state = run(&rules, "#..#.#..##......###...###", 20);
+        assert_eq!(state.sum_plant_indices().unwrap(), 325);
+    }
+
+    #[test]
+    fn test_cycle_extrapolation() {
+        let mut num_longer_periods = 0;
+        for number in 0..=255 {
+            let rules = Rules::from_wolfram(number);
+            for initial in ["#", "##.#", "#..###.#"] {
+                let mut extrapolated = Simulation::new(State::from_string(initial), rules.clone());
+                if let Some(cycle) = extrapolated.run_until(100) {
+                    num_longer_periods += (cycle.period > 1) as i32;
+                    for generation in [150, 151, 152, 153] {
+                        assert_eq!(
+                            extrapolated.sum_plant_indices(generation).ok(),
+                            run(&rules, initial, generation as usize)
+                                .sum_plant_indices()
+                                .ok()
+                        );
+                    }
+                }
+            }
+        }
+        assert!(num_longer_periods > 0);
+    }
+
+    #[test]
+    fn test_long_tape() {
+        // Rule 90 reproduces Pascal's triangle modulo 2.
+        let rules = Rules::from_wolfram(90);
+        let mut state = State::from_string("#");
+        for generation in 1..300u32 {
+            state = state.next(&rules);
+            assert_eq!(state.count_plants().unwrap(), 1 << generation.count_ones());
+            assert_eq!(state.sum_plant_indices().unwrap(), 0);
+            assert_eq!(state.index(), -(generation as i64));
+            assert!(state.get(generation as i64));
+        }
+        assert_eq!(state.len(), 2 * 299 + 1);
+    }
+
+    #[test]
+    fn test_queries() {
+        // Rule 6 moves most patterns two cells to the left every two generations.
+        let rules = Rules::from_wolfram(6);
+        let mut simulation = Simulation::new(State::from_string("#.#"), rules.clone());
+        for generation in [0, 1, 3, 7, 25, 58] {
+            let expected = run(&rules, "#.#", generation as usize);
+            assert_eq!(simulation.state_at(generation), expected);
+            assert_eq!(
+                simulation.count_plants(generation).unwrap(),
+                expected.count_plants().unwrap()
+            );
+            assert_eq!(
+                simulation.sum_plant_indices(generation).unwrap(),
+                expected.sum_plant_indices().unwrap()
+            );
+        }
+        assert_eq!(simulation.state_at(2), run(&rules, "#.#", 2));
+
+        let state = simulation.state_at(2_000_000_000);
+        let expected = run(&rules, "#.#", 2).shifted(-1_999_999_998);
+        assert_eq!(state, expected);
+        assert_eq!(state.leftmost_plant().unwrap(), Some(expected.index()));
+        assert_eq!(
+            state.rightmost_plant().unwrap(),
+            Some(expected.index() + expected.len() as i64 - 1)
+        );
+    }
+
+    #[test]
+    fn test_alternating_background() {
+        // Rule 51 inverts every cell, including the empty background.
+        let rules = Rules::from_wolfram(51);
+        let state = run(&rules, "#.##", 1);
+        assert!(state.background());
+        assert_eq!(state.to_string(), ".#..");
+        assert_eq!(state.index(), 0);
+        assert!(state.get(-5) && !state.get(0) && state.get(1) && !state.get(3));
+        assert!(state.sum_plant_indices().is_err());
+        assert_eq!(run(&rules, "#.##", 2), State::from_string("#.##"));
+
+        // Rule 1 only grows plants where the whole neighbourhood was empty.
+        let rules = Rules::from_wolfram(1);
+        assert_eq!(run(&rules, "#..#", 1).to_string(), "......");
+        assert_eq!(run(&rules, "#..#", 1).index(), -1);
+        assert_eq!(run(&rules, "#..#", 2).to_string(), "####");
+        assert_eq!(run(&rules, "#..#", 2).index(), 0);
+
+        let mut simulation = Simulation::new(State::from_string("#..#"), rules);
+        assert!(simulation.sum_plant_indices(1_000_000_001).is_err());
+        assert_eq!(simulation.sum_plant_indices(1_000_000_000).unwrap(), 6);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        assert!(Rules::from_lines(["..#. => #"]).is_err());
+        assert!(Rules::from_lines(["..#.. => #", "... => #"]).is_err());
+        assert!(Rules::from_lines(Vec::<String>::new()).is_err());
+    }
+
+    #[test]
+    fn test_rule_diagnostics() {
+        let parse_error =
+            |lines: &[&str], missing| Rules::parse(lines, 3, missing).unwrap_err().to_string();
+        assert_eq!(
+            parse_error(
+                &[
+                    "..#.. => #",
+                    "..#. => #",
+                    "..x.. => #",
+                    "..#..=>#",
+                    "..#.. => #"
+                ],
+                MissingRules::Empty
+            ),
+            "line 4: pattern has even length 4\n\
+             line 5: invalid character 'x'\n\
+             line 6: expected `<pattern> => <result>`\n\
+             line 7: duplicate of the rule on line 3"
+        );
+        assert_eq!(
+            parse_error(&["### => #", "# => .", "### => ."], MissingRules::Empty),
+            "line 4: pattern has length 1, expected 3 as on line 3\n\
+             line 5: conflicts with the rule on line 3"
+        );
+        assert_eq!(
+            parse_error(
+                &["... => .", "..# => #", ".#. => .", ".## => #", "#.. => #", "##. => ."],
+                MissingRules::Error
+            ),
+            "missing rules for `#.#`, `###`"
+        );
+        let long = format!("{} => #", ".".repeat(MAX_PATTERN_WIDTH + 2));
+        let very_long = format!("{} => #", "#".repeat(65));
+        assert_eq!(
+            parse_error(&[&long, &very_long], MissingRules::Error),
+            "line 3: pattern too long (max 15)\n\
+             line 4: pattern too long (max 15)\n\
+             no rules"
+        );
+
+        let parsed = Rules::parse(["... => #"], 3, MissingRules::Empty).unwrap();
+        assert_eq!(
+            parsed.warnings,
+            vec!["line 3: `...` produces a plant, so the empty tape fills up"]
+        );
+    }
+}
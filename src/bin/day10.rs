@@ -1,4 +1,5 @@
 use anyhow::Error;
+use aoc2018::grid::Grid;
 use std::path::PathBuf;
 use std::{env, fs};
 
@@ -8,16 +9,15 @@ fn display(points: &[Point]) {
     let min_y = points.iter().map(|p| p.py).min().unwrap();
     let max_y = points.iter().map(|p| p.py).max().unwrap();
 
-    let mut m = vec![vec![0; (max_x - min_x + 1) as usize]; (max_y - min_y + 1) as usize];
+    let mut m = Grid::new(
+        (max_x - min_x + 1) as usize,
+        (max_y - min_y + 1) as usize,
+        '.',
+    );
     for point in points.iter() {
-        m[(point.py - min_y) as usize][(point.px - min_x) as usize] = 1;
-    }
-    for line in m.iter() {
-        for val in line.iter() {
-            print!("{}", if *val == 1 { '#' } else { '.' });
-        }
-        println!();
+        m[((point.px - min_x) as usize, (point.py - min_y) as usize)] = '#';
     }
+    println!("{}", m);
 }
 
 fn solve(points: &mut [Point]) {
@@ -1,15 +1,81 @@
 use anyhow::Error;
+use aoc2018::grid::Grid;
 use std::env;
+use std::fs::read_to_string;
 use std::iter::zip;
+use std::path::Path;
 
 const GRID_SIZE: i32 = 300;
 
-fn get_input() -> Result<i32, Error> {
+/// A rectangular field of values, addressed with 1-based `(x, y)` coordinates.
+trait CellValues {
+    fn width(&self) -> i32;
+    fn height(&self) -> i32;
+    fn value(&self, x: i32, y: i32) -> i32;
+}
+
+struct FuelCells {
+    grid_serial_number: i32,
+}
+
+impl CellValues for FuelCells {
+    fn width(&self) -> i32 {
+        GRID_SIZE
+    }
+    fn height(&self) -> i32 {
+        GRID_SIZE
+    }
+    fn value(&self, x: i32, y: i32) -> i32 {
+        get_power_level(self.grid_serial_number, x, y)
+    }
+}
+
+struct FileGrid {
+    values: Grid<i32>,
+}
+
+impl FileGrid {
+    fn from_csv(input: &str) -> Result<Self, Error> {
+        let values = input
+            .lines()
+            .filter(|l| !l.trim().is_empty())
+            .map(|l| {
+                l.split(',')
+                    .map(|v| v.trim().parse::<i32>())
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let values = Grid::from_rows(values)?;
+        if values.width() == 0 || values.height() == 0 {
+            Err(Error::msg("Empty grid"))?
+        }
+        Ok(Self { values })
+    }
+}
+
+impl CellValues for FileGrid {
+    fn width(&self) -> i32 {
+        self.values.width() as i32
+    }
+    fn height(&self) -> i32 {
+        self.values.height() as i32
+    }
+    fn value(&self, x: i32, y: i32) -> i32 {
+        self.values[((x - 1) as usize, (y - 1) as usize)]
+    }
+}
+
+/// The puzzle input is either a grid serial number or the path to a CSV file.
+fn get_input() -> Result<Box<dyn CellValues>, Error> {
     let mut args = env::args();
     if args.len() < 2 {
         Err(Error::msg("Missing puzzle input argument"))
     } else {
-        Ok(args.nth(1).unwrap().parse::<i32>()?)
+        let arg = args.nth(1).unwrap();
+        Ok(match arg.parse::<i32>() {
+            Ok(grid_serial_number) => Box::new(FuelCells { grid_serial_number }),
+            Err(_) => Box::new(FileGrid::from_csv(&read_to_string(Path::new(&arg))?)?),
+        })
     }
 }
 
@@ -18,34 +84,28 @@ fn get_power_level(grid_serial_number: i32, x: i32, y: i32) -> i32 {
     rack_id * (rack_id * y + grid_serial_number) / 100 % 10 - 5
 }
 
-fn solve_for_window(grid_serial_number: i32, window: i32) -> ((i32, i32), i32) {
-    let power_levels: Vec<_> = (1..(GRID_SIZE + 1))
-        .map(|y| {
-            (1..(GRID_SIZE + 1))
-                .map(|x| get_power_level(grid_serial_number, x, y))
-                .collect::<Vec<_>>()
-        })
-        .collect();
-
-    let my_sliding_sum = |row: &Vec<i32>| sliding_sum(row.iter(), window);
-    let square_power_levels: Vec<Vec<i32>> = transpose(
-        transpose(power_levels.iter().map(my_sliding_sum).collect())
-            .iter()
-            .map(my_sliding_sum)
-            .collect::<Vec<Vec<i32>>>(),
-    );
+fn solve_for_window(cells: &dyn CellValues, window: i32) -> ((i32, i32), i32) {
+    let power_levels = Grid::from_fn(cells.width() as usize, cells.height() as usize, |x, y| {
+        cells.value(x as i32 + 1, y as i32 + 1)
+    });
+
+    let sum_rows = |grid: &Grid<i32>| {
+        Grid::from_rows(
+            grid.rows()
+                .map(|row| sliding_sum(row.iter(), window))
+                .collect(),
+        )
+        .unwrap()
+    };
+    let square_power_levels = sum_rows(&sum_rows(&power_levels).transpose()).transpose();
     let (index, val) = square_power_levels
         .iter()
-        .flatten()
         .enumerate()
         .max_by_key(|(_i, el)| *el)
         .unwrap();
-    let new_grid_size = square_power_levels.len() as i32;
+    let new_width = square_power_levels.width() as i32;
     (
-        (
-            index as i32 % new_grid_size + 1,
-            index as i32 / new_grid_size + 1,
-        ),
+        (index as i32 % new_width + 1, index as i32 / new_width + 1),
         *val,
     )
 }
@@ -63,23 +123,14 @@ where
         })
 }
 
-fn transpose(mat: Vec<Vec<i32>>) -> Vec<Vec<i32>> {
-    let num_rows = mat.len();
-    let num_cols = mat[0].len();
-
-    (0..num_cols)
-        .map(|c| (0..num_rows).map(|r| mat[r][c]).collect())
-        .collect()
-}
-
-fn solve_part_one(grid_serial_number: i32) -> (i32, i32) {
-    solve_for_window(grid_serial_number, 3).0
+fn solve_part_one(cells: &dyn CellValues) -> (i32, i32) {
+    solve_for_window(cells, 3).0
 }
 
-fn solve_part_two(grid_serial_number: i32) -> (i32, i32, i32) {
-    (1..GRID_SIZE + 1)
+fn solve_part_two(cells: &dyn CellValues) -> (i32, i32, i32) {
+    (1..i32::min(cells.width(), cells.height()) + 1)
         .map(|window| {
-            let ((row, col), value) = solve_for_window(grid_serial_number, window);
+            let ((row, col), value) = solve_for_window(cells, window);
             ((row, col, window), value)
         })
         .max_by_key(|(_, val)| *val)
@@ -88,13 +139,13 @@ fn solve_part_two(grid_serial_number: i32) -> (i32, i32, i32) {
 }
 
 fn main() -> Result<(), Error> {
-    let grid_serial_number = get_input()?;
+    let cells = get_input()?;
     {
-        let (row, col) = solve_part_one(grid_serial_number);
+        let (row, col) = solve_part_one(cells.as_ref());
         println!("{},{}", row, col);
     }
     {
-        let (row, col, window) = solve_part_two(grid_serial_number);
+        let (row, col, window) = solve_part_two(cells.as_ref());
         println!("{},{},{}", row, col, window);
     }
     Ok(())
@@ -122,23 +173,26 @@ mod tests {
     #[rstest]
     fn test_sliding_sum() {
         assert_eq!(
-            sliding_sum(vec![1, 2, 3, 4, 5, 6].iter(), 3),
+            sliding_sum([1, 2, 3, 4, 5, 6].iter(), 3),
             vec![6, 9, 12, 15]
         );
     }
 
-    #[rstest]
-    fn test_transpose() {
-        assert_eq!(
-            transpose(vec![vec![1, 2, 3], vec![4, 5, 6]]),
-            vec![vec![1, 4], vec![2, 5], vec![3, 6]]
-        );
-    }
-
     #[rstest]
     #[case(18, (90,269,16))]
     #[case(42, (232,251,12))]
     fn test_part_two(#[case] grid_serial_numer: i32, #[case] solution: (i32, i32, i32)) {
-        assert_eq!(solve_part_two(grid_serial_numer), solution)
+        let cells = FuelCells {
+            grid_serial_number: grid_serial_numer,
+        };
+        assert_eq!(solve_part_two(&cells), solution)
+    }
+
+    #[rstest]
+    fn test_file_grid() {
+        let cells = FileGrid::from_csv("0,1,0,0\n0,2,3,-9\n0,4,5,0\n").unwrap();
+        assert_eq!((cells.width(), cells.height()), (4, 3));
+        assert_eq!(solve_for_window(&cells, 2), ((2, 2), 14));
+        assert_eq!(solve_part_two(&cells), (1, 1, 3));
     }
 }
@@ -1,129 +1,284 @@
 use anyhow::Error;
+use aoc2018::automaton::{MissingRules, Rules, Simulation, State};
 use std::fs::File;
-use std::io;
-use std::io::{BufRead, BufReader, Lines};
-use std::path::Path;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
 
-const NUM_RULES: i64 = 32;
+/// Pixels and the spaces between them fit in 70 characters.
+const PBM_PIXELS_PER_LINE: usize = 35;
 
 #[derive(Debug)]
-struct Rules {
-    produce_sets: Vec<i64>,
+struct Input {
+    state: State,
+    rules: Rules,
 }
 
-impl Rules {
-    fn from_lines(lines: &mut Lines<BufReader<File>>) -> Result<Self, Error> {
-        let mut produce_sets = vec![0; NUM_RULES as usize];
-        for line_result in lines {
-            let line = line_result?;
-            if line.ends_with('#') {
-                let index = line
-                    .chars()
-                    .take(5)
-                    .map(|c| (c == '#') as i64)
-                    .fold(0, |acc, el| 2 * acc + el);
-                produce_sets[index as usize] = 1;
-            }
-        }
-        Ok(Self { produce_sets })
+fn parse_input(filename: &Path, missing: MissingRules) -> Result<Input, Error> {
+    let file = File::open(filename)?;
+    let mut lines = io::BufReader::new(file).lines();
+
+    let first_line = lines.next().ok_or(Error::msg("Empty file"))??;
+    let initial = first_line
+        .strip_prefix("initial state: ")
+        .ok_or(Error::msg("line 1: expected `initial state: <pots>`"))?;
+    if let Some(c) = initial.chars().find(|c| *c != '#' && *c != '.') {
+        Err(Error::msg(format!("line 1: invalid character '{}'", c)))?
+    }
+    let start_state = State::from_string(initial);
+    if !lines
+        .next()
+        .ok_or(Error::msg("line 2: missing rules"))??
+        .is_empty()
+    {
+        Err(Error::msg("line 2: expected an empty line"))?
     }
-    fn produce(&self, combo: i64) -> i64 {
-        self.produce_sets[combo as usize]
+
+    let parsed = Rules::parse(lines.collect::<Result<Vec<_>, _>>()?, 3, missing)?;
+    for warning in parsed.warnings.iter() {
+        eprintln!("Warning: {}", warning);
     }
+
+    let result = Input {
+        state: start_state,
+        rules: parsed.rules,
+    };
+    Ok(result)
 }
 
-#[derive(Debug)]
-struct Input {
-    state: State,
-    rules: Rules,
+#[derive(Clone, Copy)]
+enum Query {
+    Count,
+    Sum,
+    Leftmost,
+    Rightmost,
+    Pattern,
 }
 
-#[derive(Debug, Clone)]
-struct State {
-    state: Vec<i64>,
-    index: i64,
+#[derive(Default)]
+struct Options {
+    rule: Option<u8>,
+    generations: Option<Vec<u64>>,
+    queries: Vec<Query>,
+    render: Option<usize>,
+    pbm: Option<PathBuf>,
+    strict: bool,
 }
 
-impl State {
-    fn from_string(s: &str) -> Self {
-        Self {
-            state: s.chars().map(|c| (c == '#') as i64).collect(),
-            index: 0,
+/// Flags:
+/// * `--rule <number>`: run an elementary automaton from a single plant instead of the puzzle
+/// * `--generations <n>[,<n>...]`: the generations to report on instead of 20 and 50000000000
+/// * `--count`, `--sum`, `--leftmost`, `--rightmost`, `--pattern`: report these properties of
+///   each generation instead of only the plant sum
+/// * `--render <n>`: print the space-time diagram of generations `0..=n`
+/// * `--pbm <file>`: with `--render`, write the diagram as a PBM image instead
+/// * `--strict`: treat a rule file that does not cover every pattern as an error
+fn parse_options(args: &[String]) -> Result<Options, Error> {
+    let mut options = Options::default();
+    let mut args = args.iter().skip(1);
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or(Error::msg(format!("Missing value for {}", flag)))
+        };
+        match flag.as_str() {
+            "--rule" => options.rule = Some(value()?.parse()?),
+            "--generations" => {
+                options.generations = Some(
+                    value()?
+                        .split(',')
+                        .map(|g| g.parse())
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            "--count" => options.queries.push(Query::Count),
+            "--sum" => options.queries.push(Query::Sum),
+            "--leftmost" => options.queries.push(Query::Leftmost),
+            "--rightmost" => options.queries.push(Query::Rightmost),
+            "--pattern" => options.queries.push(Query::Pattern),
+            "--render" => options.render = Some(value()?.parse()?),
+            "--pbm" => options.pbm = Some(PathBuf::from(value()?)),
+            "--strict" => options.strict = true,
+            _ => Err(Error::msg(format!("Unknown argument {}", flag)))?,
         }
     }
+    Ok(options)
+}
 
-    fn sum_plant_indices(&self) -> i64 {
-        self.state
-            .iter()
-            .enumerate()
-            .filter(|(_i, val)| val != &&0)
-            .map(|(i, _val)| i as i64 + self.index)
-            .sum()
-    }
-
-    fn next(&self, rules: &Rules) -> Self {
-        let numeric = self
-            .state
-            .iter()
-            .chain([0, 0, 0].iter())
-            .fold(vec![0], |mut acc, el| {
-                acc.push((2 * acc.last().unwrap() + *el) % NUM_RULES);
-                acc
-            });
-        let next_state_it = numeric.iter().map(|v| rules.produce(*v));
-        let num_zeros = next_state_it.clone().take_while(|v| *v == 0).count();
-        State {
-            state: next_state_it.skip(num_zeros).collect(),
-            index: self.index - 3 + (num_zeros as i64),
+fn query(simulation: &mut Simulation, num_iterations: u64, query: Query) -> Result<String, Error> {
+    let format_pot = |pot: Option<i64>| pot.map_or("none".to_string(), |p| p.to_string());
+    Ok(match query {
+        Query::Count => simulation.count_plants(num_iterations)?.to_string(),
+        Query::Sum => simulation.sum_plant_indices(num_iterations)?.to_string(),
+        Query::Leftmost => format_pot(simulation.state_at(num_iterations).leftmost_plant()?),
+        Query::Rightmost => format_pot(simulation.state_at(num_iterations).rightmost_plant()?),
+        Query::Pattern => {
+            let state = simulation.state_at(num_iterations);
+            format!(
+                "{} from pot {}{}",
+                state,
+                state.index(),
+                if state.background() {
+                    ", surrounded by plants"
+                } else {
+                    ""
+                }
+            )
         }
+    })
+}
+
+fn space_time(input: &Input, num_iterations: usize) -> Vec<State> {
+    let mut states = vec![input.state.clone()];
+    for _ in 0..num_iterations {
+        states.push(states.last().unwrap().next(&input.rules));
     }
+    states
 }
 
-fn parse_input(filename: &Path) -> Result<Input, Error> {
-    let file = File::open(filename)?;
-    let mut lines = io::BufReader::new(file).lines();
+/// The range of absolute pot indices covering every plant in any of the states. When some
+/// generations have a full background, one extra pot on either side shows where it begins.
+fn pot_range(states: &[State]) -> (i64, i64) {
+    let non_empty = || states.iter().filter(|s| !s.is_empty());
+    let first = non_empty().map(|s| s.index()).min().unwrap_or(0);
+    let last = non_empty()
+        .map(|s| s.index() + s.len() as i64 - 1)
+        .max()
+        .unwrap_or(0);
+    let margin = states.iter().any(|s| s.background()) as i64;
+    (first.min(0) - margin, last.max(0) + margin)
+}
 
-    let start_state = State::from_string(
-        lines
-            .next()
-            .ok_or(Error::msg("Empty file"))??
-            .rsplit_once(' ')
-            .ok_or(Error::msg("Invalid input file"))?
-            .1,
+/// One row per generation, prefixed by the generation number and followed by the plant sum. The
+/// header marks the column of pot 0.
+fn render_text(states: &[State]) -> String {
+    let (first, last) = pot_range(states);
+    let margin = states.len().to_string().len();
+    let mut result = format!(
+        "{:margin$} {:>width$}\n",
+        "",
+        "0",
+        width = (1 - first) as usize
     );
-    lines.next().ok_or(Error::msg("Invalid input file"))??;
-
-    let rules = Rules::from_lines(&mut lines)?;
-
-    let result = Input {
-        state: start_state,
-        rules,
-    };
-    Ok(result)
+    for (generation, state) in states.iter().enumerate() {
+        let row: String = (first..=last)
+            .map(|pot| if state.get(pot) { '#' } else { '.' })
+            .collect();
+        let sum = match state.sum_plant_indices() {
+            Ok(sum) => sum.to_string(),
+            Err(_) => "infinite".to_string(),
+        };
+        result += &format!("{:>margin$} {} {}\n", generation, row, sum);
+    }
+    result
 }
 
-fn solve(input: &Input, num_iterations: i64) -> i64 {
-    let rules = &input.rules;
-    let mut state = input.state.clone();
+/// A plain PBM image with one row of pixels per generation. Rows are split over several lines
+/// to keep each line within the 70 characters the format recommends.
+fn render_pbm(states: &[State]) -> String {
+    let (first, last) = pot_range(states);
+    let mut result = format!("P1\n{} {}\n", last - first + 1, states.len());
+    for state in states {
+        let row: Vec<_> = (first..=last)
+            .map(|pot| if state.get(pot) { "1" } else { "0" })
+            .collect();
+        for line in row.chunks(PBM_PIXELS_PER_LINE) {
+            result += &line.join(" ");
+            result += "\n";
+        }
+    }
+    result
+}
 
-    for i in 0..num_iterations {
-        let old_state = state.clone();
-        state = state.next(rules);
+fn main() -> Result<(), Error> {
+    let args: Vec<String> = env::args().collect();
+    let options = parse_options(&args)?;
+    let input = match options.rule {
+        Some(number) => Input {
+            state: State::from_string("#"),
+            rules: Rules::from_wolfram(number),
+        },
+        None => parse_input(
+            Path::new("data/input12.txt"),
+            if options.strict {
+                MissingRules::Error
+            } else {
+                MissingRules::Empty
+            },
+        )?,
+    };
 
-        if old_state.state == state.state {
-            let sum_diff = state.sum_plant_indices() - old_state.sum_plant_indices();
-            return state.sum_plant_indices() + (num_iterations - i - 1) * sum_diff;
+    if let Some(num_iterations) = options.render {
+        let states = space_time(&input, num_iterations);
+        match options.pbm {
+            Some(path) => fs::write(path, render_pbm(&states))?,
+            None => print!("{}", render_text(&states)),
+        }
+    } else {
+        let mut simulation = Simulation::new(input.state, input.rules);
+        for num_iterations in options.generations.unwrap_or(vec![20, 50000000000]) {
+            if options.queries.is_empty() {
+                println!("{}", simulation.sum_plant_indices(num_iterations)?);
+                continue;
+            }
+            println!("Generation {}:", num_iterations);
+            for q in options.queries.iter() {
+                let name = match q {
+                    Query::Count => "count",
+                    Query::Sum => "sum",
+                    Query::Leftmost => "leftmost",
+                    Query::Rightmost => "rightmost",
+                    Query::Pattern => "pattern",
+                };
+                let answer = query(&mut simulation, num_iterations, *q)
+                    .unwrap_or_else(|e| e.to_string().to_lowercase());
+                println!("  {}: {}", name, answer);
+            }
         }
     }
 
-    state.sum_plant_indices()
+    Ok(())
 }
 
-fn main() -> Result<(), Error> {
-    let input = parse_input(Path::new("data/input12.txt"))?;
-    for num_iterations in [20, 50000000000] {
-        println!("{}", solve(&input, num_iterations));
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn rule_90(num_iterations: usize) -> Vec<State> {
+        let input = Input {
+            state: State::from_string("#"),
+            rules: Rules::from_wolfram(90),
+        };
+        space_time(&input, num_iterations)
     }
 
-    Ok(())
+    #[rstest]
+    fn test_render_text() {
+        let states = rule_90(2);
+        assert_eq!(pot_range(&states), (-2, 2));
+        assert_eq!(
+            render_text(&states),
+            "    0\n0 ..#.. 0\n1 .#.#. 0\n2 #...# 0\n"
+        );
+    }
+
+    #[rstest]
+    fn test_render_pbm() {
+        assert_eq!(
+            render_pbm(&rule_90(2)),
+            "P1\n5 3\n0 0 1 0 0\n0 1 0 1 0\n1 0 0 0 1\n"
+        );
+
+        let pbm = render_pbm(&rule_90(20));
+        let mut lines = pbm.lines();
+        assert_eq!(lines.next(), Some("P1"));
+        assert_eq!(lines.next(), Some("41 21"));
+        let lines: Vec<_> = lines.collect();
+        assert_eq!(lines.len(), 2 * 21);
+        assert!(lines.iter().all(|line| line.len() <= 70));
+        assert_eq!(lines[0].len(), 69);
+        let first_row: Vec<_> = (0..41).map(|i| if i == 20 { "1" } else { "0" }).collect();
+        assert_eq!(lines[0..2].join(" "), first_row.join(" "));
+    }
 }
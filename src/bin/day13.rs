@@ -1,6 +1,16 @@
 use anyhow::Error;
+use aoc2018::grid::Grid;
+use crossterm::event::{self, Event as KeyEvent, KeyCode, KeyEventKind};
+use crossterm::style::{Color, Stylize};
+use crossterm::{cursor, execute, queue, terminal};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
 use std::fs::read_to_string;
-use std::path::Path;
+use std::hash::{Hash, Hasher};
+use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs};
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 enum Track {
@@ -16,17 +26,72 @@ impl Track {
     fn from_char(c: char) -> Result<Self, Error> {
         Ok(match c {
             ' ' => Track::None,
-            '|' | 'v' | '^' => Track::Vertical,
-            '-' | '>' | '<' => Track::Horizontal,
+            '|' => Track::Vertical,
+            '-' => Track::Horizontal,
             '/' => Track::CurveForward,
             '\\' => Track::CurveBackward,
             '+' => Track::Intersection,
             _ => Err(Error::msg("Invalid input data"))?,
         })
     }
+
+    fn to_char(self) -> char {
+        match self {
+            Track::None => ' ',
+            Track::Vertical => '|',
+            Track::Horizontal => '-',
+            Track::CurveForward => '/',
+            Track::CurveBackward => '\\',
+            Track::Intersection => '+',
+        }
+    }
+
+    /// Whether the track can leave this cell in the given direction. Curves can connect in any
+    /// direction until they are resolved against their neighbours.
+    fn connects(self, direction: Direction) -> bool {
+        match self {
+            Track::None => false,
+            Track::Vertical => matches!(direction, Direction::Up | Direction::Down),
+            Track::Horizontal => matches!(direction, Direction::Left | Direction::Right),
+            Track::CurveForward | Track::CurveBackward | Track::Intersection => true,
+        }
+    }
+
+    /// The piece of track connecting exactly the given directions.
+    fn from_connections(connections: &[Direction]) -> Self {
+        let connects = |d| connections.contains(&d);
+        match (
+            connects(Direction::Up),
+            connects(Direction::Down),
+            connects(Direction::Left),
+            connects(Direction::Right),
+        ) {
+            (true, true, true, true) => Track::Intersection,
+            (true, true, false, false) => Track::Vertical,
+            (false, false, true, true) => Track::Horizontal,
+            (false, true, false, true) | (true, false, true, false) => Track::CurveForward,
+            (false, true, true, false) | (true, false, false, true) => Track::CurveBackward,
+            _ => Track::None,
+        }
+    }
+
+    /// The two pairs of directions a curve may connect, depending on which corner it forms.
+    fn curve_options(self) -> Option<[[Direction; 2]; 2]> {
+        match self {
+            Track::CurveForward => Some([
+                [Direction::Down, Direction::Right],
+                [Direction::Up, Direction::Left],
+            ]),
+            Track::CurveBackward => Some([
+                [Direction::Down, Direction::Left],
+                [Direction::Up, Direction::Right],
+            ]),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy, Hash)]
 enum Turn {
     Left,
     Straight,
@@ -34,16 +99,125 @@ enum Turn {
 }
 
 impl Turn {
-    fn next(self) -> Self {
+    fn name(self) -> &'static str {
+        match self {
+            Turn::Left => "left",
+            Turn::Straight => "straight",
+            Turn::Right => "right",
+        }
+    }
+
+    fn to_char(self) -> char {
         match self {
-            Turn::Left => Turn::Straight,
-            Turn::Straight => Turn::Right,
-            Turn::Right => Turn::Left,
+            Turn::Left => 'L',
+            Turn::Straight => 'S',
+            Turn::Right => 'R',
         }
     }
+
+    fn from_char(c: char) -> Option<Self> {
+        Some(match c {
+            'L' => Turn::Left,
+            'S' => Turn::Straight,
+            'R' => Turn::Right,
+            _ => None?,
+        })
+    }
 }
 
-#[derive(Clone, Copy)]
+/// A SplitMix64 generator, which is plenty for picking turns reproducibly.
+#[derive(Debug, PartialEq, Clone, Hash)]
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// How a cart chooses its way at intersections.
+#[derive(Debug, PartialEq, Clone, Hash)]
+enum TurnPolicy {
+    /// Takes the turns in order and starts over; the first one is the next to be taken.
+    Cycle(Vec<Turn>),
+    /// Picks every turn at random.
+    Random(Rng),
+}
+
+impl Default for TurnPolicy {
+    fn default() -> Self {
+        TurnPolicy::Cycle(vec![Turn::Left, Turn::Straight, Turn::Right])
+    }
+}
+
+impl TurnPolicy {
+    /// Parses a comma separated sequence of turns like `L,L,S,R`, or `random:<seed>`.
+    fn parse(text: &str) -> Result<Self, Error> {
+        if let Some(seed) = text.strip_prefix("random:") {
+            return Ok(TurnPolicy::Random(Rng {
+                state: seed.parse()?,
+            }));
+        }
+        let turns = text
+            .split(',')
+            .map(|t| {
+                let mut chars = t.chars();
+                match (chars.next().and_then(Turn::from_char), chars.next()) {
+                    (Some(turn), None) => Ok(turn),
+                    _ => Err(Error::msg(format!("Invalid turn `{}`", t))),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(TurnPolicy::Cycle(turns))
+    }
+
+    fn to_text(&self) -> String {
+        match self {
+            TurnPolicy::Cycle(turns) => {
+                let turns: Vec<_> = turns.iter().map(|t| t.to_char().to_string()).collect();
+                turns.join(",")
+            }
+            TurnPolicy::Random(rng) => format!("random:{}", rng.state),
+        }
+    }
+
+    /// The policy for a single cart. Random policies get a different seed for every cart, so
+    /// that the carts do not all turn alike.
+    fn for_cart(&self, id: usize) -> Self {
+        match self {
+            TurnPolicy::Cycle(_) => self.clone(),
+            TurnPolicy::Random(rng) => TurnPolicy::Random(Rng {
+                state: Rng {
+                    state: rng.state.wrapping_add(id as u64),
+                }
+                .next_u64(),
+            }),
+        }
+    }
+
+    fn next(&mut self) -> Turn {
+        match self {
+            TurnPolicy::Cycle(turns) => {
+                turns.rotate_left(1);
+                *turns.last().unwrap()
+            }
+            TurnPolicy::Random(rng) => [Turn::Left, Turn::Straight, Turn::Right][rng.below(3)],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Hash)]
 enum Direction {
     Up,
     Down,
@@ -52,6 +226,51 @@ enum Direction {
 }
 
 impl Direction {
+    const ALL: [Direction; 4] = [
+        Direction::Up,
+        Direction::Down,
+        Direction::Left,
+        Direction::Right,
+    ];
+
+    fn opposite(self) -> Self {
+        match self {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        }
+    }
+
+    /// The neighbouring location in this direction. Moving off the top or left edge wraps to a
+    /// location outside the map.
+    fn step_from(self, (x, y): (usize, usize)) -> (usize, usize) {
+        match self {
+            Direction::Up => (x, y.wrapping_sub(1)),
+            Direction::Down => (x, y + 1),
+            Direction::Left => (x.wrapping_sub(1), y),
+            Direction::Right => (x + 1, y),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+            Direction::Left => "left",
+            Direction::Right => "right",
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Left => '<',
+            Direction::Right => '>',
+        }
+    }
+
     fn from_char(c: char) -> Option<Direction> {
         Some(match c {
             '<' => Direction::Left,
@@ -63,16 +282,18 @@ impl Direction {
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, PartialEq, Clone, Hash)]
 struct Cart {
-    location: (i32, i32),
+    id: usize,
+    location: (usize, usize),
     direction: Direction,
-    next_turn: Turn,
+    turns: TurnPolicy,
 }
 
 impl Cart {
-    fn turn(&mut self) {
-        match self.next_turn {
+    fn turn(&mut self) -> Turn {
+        let turn = self.turns.next();
+        match turn {
             Turn::Straight => {}
             Turn::Left => {
                 self.direction = match self.direction {
@@ -91,25 +312,20 @@ impl Cart {
                 }
             }
         }
-        self.next_turn = self.next_turn.next();
+        turn
     }
 
     fn _step_forward(&mut self) {
-        let (x, y) = self.location;
-        self.location = match self.direction {
-            Direction::Up => (x, y - 1),
-            Direction::Down => (x, y + 1),
-            Direction::Left => (x - 1, y),
-            Direction::Right => (x + 1, y),
-        };
+        self.location = self.direction.step_from(self.location);
     }
 
-    fn step(&mut self, map: &Map) {
+    /// Moves the cart one step, returning the turn it took if it crossed an intersection.
+    fn step(&mut self, map: &Map) -> Result<Option<Turn>, Error> {
         self._step_forward();
         let (x, y) = self.location;
-        match (map[y as usize][x as usize], &self.direction) {
+        match (track_at(map, self.location), &self.direction) {
             (Track::Horizontal, _) | (Track::Vertical, _) => {}
-            (Track::None, _) => panic!("Programming error"),
+            (Track::None, _) => Err(Error::msg(format!("Cart ran off the track at {},{}", x, y)))?,
             (Track::CurveForward, Direction::Down) => self.direction = Direction::Left,
             (Track::CurveForward, Direction::Left) => self.direction = Direction::Down,
             (Track::CurveForward, Direction::Up) => self.direction = Direction::Right,
@@ -118,67 +334,1044 @@ impl Cart {
             (Track::CurveBackward, Direction::Right) => self.direction = Direction::Down,
             (Track::CurveBackward, Direction::Up) => self.direction = Direction::Left,
             (Track::CurveBackward, Direction::Left) => self.direction = Direction::Up,
-            (Track::Intersection, _) => self.turn(),
+            (Track::Intersection, _) => return Ok(Some(self.turn())),
         }
+        Ok(None)
     }
 }
 
-fn get_crashed_cart(carts: &[Cart], moved_index: usize) -> Option<usize> {
-    carts
-        .iter()
-        .enumerate()
-        .filter(|(i, c)| *i != moved_index && c.location == carts[moved_index].location)
-        .map(|(i, _c)| i)
-        .next()
+fn track_at(map: &Map, (x, y): (usize, usize)) -> Track {
+    map.get(x, y).copied().unwrap_or(Track::None)
+}
+
+/// Checks that every piece of track connects to its neighbours, that every curve forms a
+/// single corner, and that every cart stands on track leading in its direction.
+fn validate(map: &Map, carts: &[Cart]) -> Result<(), Error> {
+    let mut problems = vec![];
+
+    let connections = Grid::from_fn(map.width(), map.height(), |x, y| {
+        let track = map[(x, y)];
+        let Some(options) = track.curve_options() else {
+            return Direction::ALL
+                .into_iter()
+                .filter(|d| track.connects(*d))
+                .collect::<Vec<_>>();
+        };
+        let valid: Vec<_> = options
+            .into_iter()
+            .filter(|option| {
+                option
+                    .iter()
+                    .all(|d| track_at(map, d.step_from((x, y))).connects(d.opposite()))
+            })
+            .collect();
+        match valid[..] {
+            [option] => option.to_vec(),
+            [] => {
+                problems.push(format!("disconnected curve at {},{}", x, y));
+                vec![]
+            }
+            _ => {
+                problems.push(format!("ambiguous curve at {},{}", x, y));
+                vec![]
+            }
+        }
+    });
+
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            for direction in connections[(x, y)].iter() {
+                let (nx, ny) = direction.step_from((x, y));
+                let connected = match connections.get(nx, ny) {
+                    // Unresolved curves have been reported already.
+                    Some(c) if c.is_empty() && map[(nx, ny)].curve_options().is_some() => true,
+                    Some(c) => c.contains(&direction.opposite()),
+                    None => false,
+                };
+                if !connected {
+                    problems.push(format!(
+                        "disconnected track end at {},{} going {}",
+                        x,
+                        y,
+                        direction.name()
+                    ));
+                }
+            }
+        }
+    }
+
+    for cart in carts {
+        let (x, y) = cart.location;
+        let on_track = match connections.get(x, y) {
+            Some(c) if c.is_empty() && map[(x, y)].curve_options().is_some() => true,
+            Some(c) => c.contains(&cart.direction),
+            None => false,
+        };
+        if !on_track {
+            problems.push(format!(
+                "cart at {},{} is not on track going {}",
+                x,
+                y,
+                cart.direction.name()
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::msg(format!(
+            "Invalid track layout:\n{}",
+            problems.join("\n")
+        )))
+    }
+}
+
+/// Something that happened to a cart while it moved during the given tick.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Event {
+    Turn {
+        tick: usize,
+        cart: usize,
+        location: (usize, usize),
+        turn: Turn,
+    },
+    Crash {
+        tick: usize,
+        carts: [usize; 2],
+        location: (usize, usize),
+    },
+    Removal {
+        tick: usize,
+        cart: usize,
+        location: (usize, usize),
+    },
+}
+
+impl Event {
+    fn to_text(self) -> String {
+        match self {
+            Event::Turn {
+                tick,
+                cart,
+                location: (x, y),
+                turn,
+            } => format!(
+                "tick {}: cart {} turned {} at {},{}",
+                tick,
+                cart,
+                turn.name(),
+                x,
+                y
+            ),
+            Event::Crash {
+                tick,
+                carts: [moved, hit],
+                location: (x, y),
+            } => format!(
+                "tick {}: cart {} crashed into cart {} at {},{}",
+                tick, moved, hit, x, y
+            ),
+            Event::Removal {
+                tick,
+                cart,
+                location: (x, y),
+            } => format!("tick {}: cart {} removed at {},{}", tick, cart, x, y),
+        }
+    }
+
+    fn to_json(self) -> String {
+        match self {
+            Event::Turn {
+                tick,
+                cart,
+                location: (x, y),
+                turn,
+            } => format!(
+                r#"{{"tick":{},"event":"turn","cart":{},"x":{},"y":{},"turn":"{}"}}"#,
+                tick,
+                cart,
+                x,
+                y,
+                turn.name()
+            ),
+            Event::Crash {
+                tick,
+                carts: [moved, hit],
+                location: (x, y),
+            } => format!(
+                r#"{{"tick":{},"event":"crash","carts":[{},{}],"x":{},"y":{}}}"#,
+                tick, moved, hit, x, y
+            ),
+            Event::Removal {
+                tick,
+                cart,
+                location: (x, y),
+            } => format!(
+                r#"{{"tick":{},"event":"removal","cart":{},"x":{},"y":{}}}"#,
+                tick, cart, x, y
+            ),
+        }
+    }
+}
+
+/// Moves the carts one at a time, in reading order of where they stand at the start of each
+/// tick.
+struct Simulation<'a> {
+    map: &'a Map,
+    /// The carts in the order in which they move during the current tick. Removed carts leave
+    /// a gap until the tick is complete.
+    carts: Vec<Option<Cart>>,
+    /// The indices into `carts` of the carts at each location, in increasing order.
+    occupied: HashMap<(usize, usize), Vec<usize>>,
+    num_carts: usize,
+    tick: usize,
+    next_cart: usize,
+    remove_crashed: bool,
 }
 
-fn solve1(map: &Map, mut carts: Vec<Cart>) -> (i32, i32) {
+impl<'a> Simulation<'a> {
+    fn new(map: &'a Map, carts: Vec<Cart>, remove_crashed: bool) -> Self {
+        let mut simulation = Self {
+            map,
+            num_carts: carts.len(),
+            carts: carts.into_iter().map(Some).collect(),
+            occupied: HashMap::new(),
+            tick: 0,
+            next_cart: 0,
+            remove_crashed,
+        };
+        simulation.start_tick();
+        simulation
+    }
+
+    /// Closes the gaps left by removed carts and puts the carts in the order in which they move.
+    fn start_tick(&mut self) {
+        self.carts.retain(Option::is_some);
+        self.carts.sort_by_key(|c| {
+            let (x, y) = c.as_ref().unwrap().location;
+            (y, x)
+        });
+        self.occupied.clear();
+        for (i, cart) in self.carts.iter().enumerate() {
+            let location = cart.as_ref().unwrap().location;
+            self.occupied.entry(location).or_default().push(i);
+        }
+    }
+
+    fn carts(&self) -> impl Iterator<Item = &Cart> {
+        self.carts.iter().flatten()
+    }
+
+    fn num_carts(&self) -> usize {
+        self.num_carts
+    }
+
+    /// One of the carts at the given location.
+    fn cart_at(&self, location: (usize, usize)) -> Option<&Cart> {
+        let index = *self.occupied.get(&location)?.first()?;
+        self.carts[index].as_ref()
+    }
+
+    fn leave(&mut self, index: usize, location: (usize, usize)) {
+        let indices = self.occupied.get_mut(&location).unwrap();
+        indices.retain(|i| *i != index);
+        if indices.is_empty() {
+            self.occupied.remove(&location);
+        }
+    }
+
+    /// Moves the next cart, returning what happened to it.
+    fn move_next(&mut self) -> Result<Vec<Event>, Error> {
+        let mut events = vec![];
+        let tick = self.tick;
+        let cart_index = self.next_cart;
+        let cart = self.carts[cart_index].as_mut().unwrap();
+        let previous_location = cart.location;
+        let turn = cart.step(self.map)?;
+        let (id, location) = (cart.id, cart.location);
+        if let Some(turn) = turn {
+            events.push(Event::Turn {
+                tick,
+                cart: id,
+                location,
+                turn,
+            });
+        }
+        self.leave(cart_index, previous_location);
+
+        let indices = self.occupied.entry(location).or_default();
+        if let Some(&crashed_index) = indices.first() {
+            let ids = [id, self.carts[crashed_index].as_ref().unwrap().id];
+            events.push(Event::Crash {
+                tick,
+                carts: ids,
+                location,
+            });
+            if self.remove_crashed {
+                for cart in ids {
+                    events.push(Event::Removal {
+                        tick,
+                        cart,
+                        location,
+                    });
+                }
+                self.leave(crashed_index, location);
+                self.carts[cart_index] = None;
+                self.carts[crashed_index] = None;
+                self.num_carts -= 2;
+            }
+        }
+        if self.carts[cart_index].is_some() {
+            let indices = self.occupied.entry(location).or_default();
+            let position = indices.partition_point(|i| *i < cart_index);
+            indices.insert(position, cart_index);
+        }
+
+        self.next_cart += 1;
+        while self.next_cart < self.carts.len() && self.carts[self.next_cart].is_none() {
+            self.next_cart += 1;
+        }
+        if self.next_cart >= self.carts.len() {
+            self.next_cart = 0;
+            self.tick += 1;
+            self.start_tick();
+        }
+        Ok(events)
+    }
+}
+
+impl<'a> Simulation<'a> {
+    /// Continues the simulation from a snapshot.
+    fn resume(snapshot: &'a Snapshot, remove_crashed: bool) -> Self {
+        Self {
+            tick: snapshot.tick,
+            ..Self::new(&snapshot.map, snapshot.carts.clone(), remove_crashed)
+        }
+    }
+
+    /// The state between two ticks. Carts that crashed but have not been removed cannot be
+    /// drawn on the map.
+    fn snapshot(&self) -> Result<Snapshot, Error> {
+        if self.next_cart != 0 {
+            Err(Error::msg("Can only take a snapshot between ticks"))?
+        }
+        if let Some(((x, y), _)) = self.occupied.iter().find(|(_, i)| i.len() > 1) {
+            Err(Error::msg(format!("Crashed carts at {},{}", x, y)))?
+        }
+        Ok(Snapshot {
+            map: self.map.clone(),
+            carts: self.carts().cloned().collect(),
+            tick: self.tick,
+        })
+    }
+
+    /// Moves carts until the current tick is complete.
+    fn run_tick(&mut self) -> Result<Vec<Event>, Error> {
+        let tick = self.tick;
+        let mut events = vec![];
+        while self.tick == tick && self.num_carts() > 0 {
+            events.extend(self.move_next()?);
+        }
+        Ok(events)
+    }
+}
+
+/// The state of a simulation between two ticks. As text, a header with the tick and the turn
+/// policy of each cart in reading order is followed by an empty line and the map with the carts
+/// drawn on it. Each header line also names the track below its cart, so that it does not need
+/// to be inferred on restore.
+#[derive(Debug)]
+struct Snapshot {
+    map: Map,
+    carts: Vec<Cart>,
+    tick: usize,
+}
+
+impl Snapshot {
+    /// The state before the first tick.
+    fn new(map: Map, carts: Vec<Cart>) -> Self {
+        Self {
+            map,
+            carts,
+            tick: 0,
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut carts: Vec<_> = self.carts.iter().collect();
+        carts.sort_by_key(|c| (c.location.1, c.location.0));
+        let mut result = format!("tick {}\n", self.tick);
+        for cart in carts.iter() {
+            result += &format!(
+                "cart {} on {} turns {}\n",
+                cart.id,
+                self.map[cart.location].to_char(),
+                cart.turns.to_text()
+            );
+        }
+        result += "\n";
+        result + &draw(&self.map, &self.carts)
+    }
+
+    fn from_text(input: &str) -> Result<Self, Error> {
+        let (header, drawing) = input
+            .split_once("\n\n")
+            .ok_or(Error::msg("Missing empty line after the snapshot header"))?;
+        let mut lines = header.lines();
+        let tick = lines
+            .next()
+            .and_then(|l| l.strip_prefix("tick "))
+            .ok_or(Error::msg("line 1: expected `tick <n>`"))?
+            .parse()?;
+        let mut cart_lines = vec![];
+        for (i, line) in lines.enumerate() {
+            let invalid = || {
+                Error::msg(format!(
+                    "line {}: expected `cart <id> on <track> turns <policy>`",
+                    i + 2
+                ))
+            };
+            let words: Vec<_> = line.split(' ').collect();
+            let ["cart", id, "on", track, "turns", turns] = words[..] else {
+                Err(invalid())?
+            };
+            let mut track = track.chars();
+            let (Some(track), None) = (track.next(), track.next()) else {
+                Err(invalid())?
+            };
+            cart_lines.push((
+                id.parse::<usize>()?,
+                Track::from_char(track)?,
+                TurnPolicy::parse(turns)?,
+            ));
+        }
+
+        let mut carts = parse_carts(drawing);
+        if carts.len() != cart_lines.len() {
+            Err(Error::msg(format!(
+                "The header lists {} carts, but the map shows {}",
+                cart_lines.len(),
+                carts.len()
+            )))?
+        }
+        let mut lines: Vec<Vec<char>> = drawing.lines().map(|l| l.chars().collect()).collect();
+        for (cart, (id, track, turns)) in carts.iter_mut().zip(cart_lines) {
+            cart.id = id;
+            cart.turns = turns;
+            let (x, y) = cart.location;
+            lines[y][x] = track.to_char();
+        }
+        let lines: Vec<String> = lines.into_iter().map(String::from_iter).collect();
+        let map = parse_map(&lines.join("\n"))?;
+        Ok(Self { map, carts, tick })
+    }
+}
+
+/// How a simulation run ended.
+#[derive(Debug, PartialEq)]
+enum Outcome<T> {
+    Found(T),
+    /// From the given tick on, the `carts` that are left return to the same state every
+    /// `period` ticks, so the answer will never be found.
+    Cycle {
+        start: usize,
+        period: usize,
+        carts: usize,
+    },
+    /// The answer was not found before the given tick.
+    TickLimit(usize),
+    NoCarts,
+}
+
+impl<T> Outcome<T> {
+    /// Describes the outcome, saying that the answer `never` happens when the carts cycle.
+    fn to_text(&self, never: &str, found: impl FnOnce(&T) -> String) -> String {
+        match self {
+            Outcome::Found(answer) => found(answer),
+            Outcome::Cycle {
+                start,
+                period,
+                carts,
+            } => format!(
+                "{}: from tick {} on, the remaining {} carts repeat every {} ticks",
+                never, start, carts, period
+            ),
+            Outcome::TickLimit(tick) => format!("No answer before tick {}", tick),
+            Outcome::NoCarts => "No carts left".to_string(),
+        }
+    }
+}
+
+/// Moves the carts one at a time and passes the events to `handle`, until it returns the
+/// answer. At the start of each tick, stops at the tick limit, or when the carts are back in a
+/// state they were in before.
+fn run<T>(
+    simulation: &mut Simulation,
+    max_ticks: Option<usize>,
+    mut handle: impl FnMut(&Simulation, Vec<Event>) -> Option<T>,
+) -> Result<Outcome<T>, Error> {
+    let mut seen: HashMap<u64, usize> = HashMap::new();
+    let mut candidate: Option<(Vec<Cart>, usize, usize)> = None;
     loop {
-        carts.sort_by_key(|c| c.location);
-        for cart_index in 0..carts.len() {
-            carts[cart_index].step(map);
-            if get_crashed_cart(&carts, cart_index).is_some() {
-                return carts[cart_index].location;
+        if simulation.num_carts() == 0 {
+            return Ok(Outcome::NoCarts);
+        }
+        if simulation.next_cart == 0 {
+            let tick = simulation.tick;
+            if max_ticks.is_some_and(|max_ticks| tick >= max_ticks) {
+                return Ok(Outcome::TickLimit(tick));
+            }
+            let mut carts: Vec<_> = simulation.carts().cloned().collect();
+            carts.sort_by_key(|c| (c.location, c.id));
+            if let Some((state, start, period)) = &candidate {
+                if tick == start + period {
+                    if *state == carts {
+                        return Ok(Outcome::Cycle {
+                            start: start - period,
+                            period: *period,
+                            carts: carts.len(),
+                        });
+                    }
+                    candidate = None;
+                }
+            }
+
+            let mut hasher = DefaultHasher::new();
+            carts.hash(&mut hasher);
+            let previous = seen.insert(hasher.finish(), tick);
+            if let (Some(previous), None) = (previous, &candidate) {
+                candidate = Some((carts, tick, tick - previous));
+            }
+        }
+        let events = simulation.move_next()?;
+        if let Some(answer) = handle(simulation, events) {
+            return Ok(Outcome::Found(answer));
+        }
+    }
+}
+
+/// The first crash, in which the first of the carts ran into the second.
+#[derive(Debug, PartialEq)]
+struct Crash {
+    tick: usize,
+    carts: [usize; 2],
+    location: (usize, usize),
+}
+
+/// The cart that is left once all others have crashed, and the number of ticks until then.
+#[derive(Debug, PartialEq)]
+struct Survivor {
+    cart: usize,
+    location: (usize, usize),
+    ticks: usize,
+}
+
+fn solve1(start: &Snapshot, max_ticks: Option<usize>) -> Result<Outcome<Crash>, Error> {
+    let mut simulation = Simulation::resume(start, false);
+    run(&mut simulation, max_ticks, |_, events| {
+        events.into_iter().find_map(|event| match event {
+            Event::Crash {
+                tick,
+                carts,
+                location,
+            } => Some(Crash {
+                tick,
+                carts,
+                location,
+            }),
+            _ => None,
+        })
+    })
+}
+
+/// Runs until the end of the first tick after which only one cart is left.
+fn solve2(start: &Snapshot, max_ticks: Option<usize>) -> Result<Outcome<Survivor>, Error> {
+    let mut simulation = Simulation::resume(start, true);
+    run(&mut simulation, max_ticks, |simulation, _| {
+        if simulation.next_cart != 0 || simulation.num_carts() != 1 {
+            return None;
+        }
+        let cart = simulation.carts().next().unwrap();
+        Some(Survivor {
+            cart: cart.id,
+            location: cart.location,
+            ticks: simulation.tick,
+        })
+    })
+}
+
+/// Prints every event of the simulation of part two, as text or as JSON lines.
+fn print_events(start: &Snapshot, format: &str, max_ticks: Option<usize>) -> Result<(), Error> {
+    let to_string = match format {
+        "text" => Event::to_text,
+        "json" => Event::to_json,
+        _ => Err(Error::msg(format!("Unknown event format {}", format)))?,
+    };
+    let mut simulation = Simulation::resume(start, true);
+    let outcome = run(&mut simulation, max_ticks, |simulation, events| {
+        for event in events {
+            println!("{}", to_string(event));
+        }
+        (simulation.next_cart == 0 && simulation.num_carts() < 2).then_some(())
+    })?;
+    if outcome != Outcome::Found(()) {
+        eprintln!(
+            "{}",
+            outcome.to_text("More than one cart is always left", |_| String::new())
+        );
+    }
+    Ok(())
+}
+
+const CART_COLORS: [Color; 6] = [
+    Color::Green,
+    Color::Yellow,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::White,
+];
+
+/// Restores the terminal when the animation ends, also when it ends with an error.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new() -> Result<Self, Error> {
+        terminal::enable_raw_mode()?;
+        execute!(stdout(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(stdout(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw_frame(
+    simulation: &Simulation,
+    crash_sites: &[(usize, usize)],
+    delay: Duration,
+    paused: bool,
+    message: Option<&str>,
+) -> Result<(), Error> {
+    let mut out = stdout();
+    queue!(out, cursor::MoveTo(0, 0))?;
+    for y in 0..simulation.map.height() {
+        for x in 0..simulation.map.width() {
+            match simulation.cart_at((x, y)) {
+                Some(cart) => {
+                    let color = CART_COLORS[cart.id % CART_COLORS.len()];
+                    write!(out, "{}", cart.direction.to_char().with(color).bold())?
+                }
+                None if crash_sites.contains(&(x, y)) => {
+                    write!(out, "{}", 'X'.with(Color::Red).bold())?
+                }
+                None => write!(out, "{}", simulation.map[(x, y)].to_char())?,
+            }
+        }
+        write!(out, "\r\n")?;
+    }
+    write!(
+        out,
+        "tick {}, {} carts, {} ms/tick{}{}",
+        simulation.tick,
+        simulation.num_carts(),
+        delay.as_millis(),
+        if paused { ", paused" } else { "" },
+        message.map_or(String::new(), |m| format!(" - {}", m))
+    )?;
+    queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+    write!(
+        out,
+        "\r\n[space] pause  [n] step  [+/-] speed  [c] next crash  [q] quit"
+    )?;
+    queue!(out, terminal::Clear(terminal::ClearType::UntilNewLine))?;
+    out.flush()?;
+    Ok(())
+}
+
+/// The most ticks the animation runs ahead looking for the next crash.
+const CRASH_SEARCH_TICKS: usize = 100_000;
+
+/// Whether `q` or Esc was pressed, without waiting for a key.
+fn quit_pressed() -> Result<bool, Error> {
+    while event::poll(Duration::ZERO)? {
+        if let KeyEvent::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press
+                && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+            {
+                return Ok(true);
             }
         }
     }
+    Ok(false)
+}
+
+/// How the animation's search for the next crash ended.
+enum CrashSearch {
+    /// The last tick run had a crash, or no carts are left.
+    Crash,
+    /// `q` or Esc was pressed.
+    Quit,
+    NotFound(String),
+}
+
+/// Runs whole ticks of the animation until one has a crash, asking `quit` after each other
+/// tick whether to stop.
+fn run_to_crash(
+    simulation: &mut Simulation,
+    crash_sites: &mut Vec<(usize, usize)>,
+    mut quit: impl FnMut() -> Result<bool, Error>,
+) -> Result<CrashSearch, Error> {
+    let max_ticks = simulation.tick + CRASH_SEARCH_TICKS;
+    let mut crashed = false;
+    let outcome = run(simulation, Some(max_ticks), |simulation, events| {
+        for event in events {
+            if let Event::Crash { location, .. } = event {
+                crash_sites.push(location);
+                crashed = true;
+            }
+        }
+        if simulation.next_cart != 0 {
+            return None;
+        }
+        if crashed {
+            return Some(Ok(CrashSearch::Crash));
+        }
+        match quit() {
+            Ok(false) => None,
+            Ok(true) => Some(Ok(CrashSearch::Quit)),
+            Err(e) => Some(Err(e)),
+        }
+    })?;
+    Ok(match outcome {
+        Outcome::Found(found) => found?,
+        Outcome::Cycle { period, .. } => CrashSearch::NotFound(format!(
+            "no further crash, the carts repeat every {} ticks",
+            period
+        )),
+        Outcome::TickLimit(_) => {
+            CrashSearch::NotFound(format!("no crash within {} ticks", CRASH_SEARCH_TICKS))
+        }
+        Outcome::NoCarts => CrashSearch::Crash,
+    })
 }
 
-fn solve2(map: &Map, mut carts: Vec<Cart>) -> (i32, i32) {
+/// Shows the simulation of part two in the terminal, one tick per frame.
+fn animate(start: &Snapshot, delay_ms: u64) -> Result<(), Error> {
+    let _guard = TerminalGuard::new()?;
+    execute!(stdout(), terminal::Clear(terminal::ClearType::All))?;
+    let mut simulation = Simulation::resume(start, true);
+    let mut crash_sites = vec![];
+    let mut delay = Duration::from_millis(delay_ms);
+    let mut paused = false;
+    let mut message = None;
+
     loop {
-        carts.sort_by_key(|c| c.location);
-        let mut cart_index = 0;
-        while cart_index < carts.len() {
-            carts[cart_index].step(map);
-            if let Some(crashed_index) = get_crashed_cart(&carts, cart_index) {
-                if crashed_index < cart_index {
-                    carts.remove(cart_index);
-                    carts.remove(crashed_index);
-                    cart_index -= 1;
-                } else {
-                    carts.remove(crashed_index);
-                    carts.remove(cart_index);
+        let finished = simulation.num_carts() <= 1;
+        draw_frame(
+            &simulation,
+            &crash_sites,
+            delay,
+            paused || finished,
+            message.as_deref(),
+        )?;
+
+        let mut step = false;
+        if !paused && !finished && !event::poll(delay)? {
+            step = true;
+        } else if let KeyEvent::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            message = None;
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char(' ') => paused = !paused,
+                KeyCode::Char('n') => step = true,
+                KeyCode::Char('+') => delay = (delay / 2).max(Duration::from_millis(10)),
+                KeyCode::Char('-') => delay = (delay * 2).min(Duration::from_secs(2)),
+                KeyCode::Char('c') if !finished => {
+                    paused = true;
+                    match run_to_crash(&mut simulation, &mut crash_sites, quit_pressed)? {
+                        CrashSearch::Crash => {}
+                        CrashSearch::Quit => return Ok(()),
+                        CrashSearch::NotFound(text) => message = Some(text),
+                    }
                 }
-                if carts.len() == 1 {
-                    return carts[0].location;
+                _ => {}
+            }
+        }
+
+        if step && !finished {
+            for event in simulation.run_tick()? {
+                if let Event::Crash { location, .. } = event {
+                    crash_sites.push(location);
                 }
-            } else {
-                cart_index += 1
             }
         }
     }
 }
 
+#[derive(Default)]
+struct Options {
+    events: Option<String>,
+    animate: Option<u64>,
+    resume: Option<PathBuf>,
+    save: Option<PathBuf>,
+    tick: Option<usize>,
+    turns: Option<TurnPolicy>,
+    cart_turns: Vec<(usize, TurnPolicy)>,
+    max_ticks: Option<usize>,
+    generate: Option<(usize, usize)>,
+    carts: usize,
+    seed: u64,
+}
+
+/// Flags:
+/// * `--events <text|json>`: print every event of part two instead of the answers
+/// * `--animate [<ms per tick>]`: show part two in the terminal
+/// * `--resume <file>`: start from a snapshot instead of the puzzle input
+/// * `--save <file> --tick <n>`: run part two up to the given tick and save a snapshot
+/// * `--turns <policy>`: how all carts turn at intersections, either a sequence like `L,S,R`
+///   (the default) or `S` to always go straight, or `random:<seed>`
+/// * `--cart-turns <id>=<policy>`: how a single cart turns, overriding `--turns`
+/// * `--max-ticks <n>`: give up on finding a crash or a survivor at tick `n`, counting the ticks
+///   before a resumed snapshot too
+/// * `--generate <width>x<height> [--carts <n>] [--seed <n>]`: print a random map instead
+fn parse_options(args: &[String]) -> Result<Options, Error> {
+    let mut options = Options::default();
+    let mut args = args.iter().skip(1).peekable();
+    while let Some(flag) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or(Error::msg(format!("Missing value for {}", flag)))
+        };
+        match flag.as_str() {
+            "--events" => options.events = Some(value()?.clone()),
+            "--animate" => {
+                options.animate = Some(
+                    args.next_if(|a| !a.starts_with("--"))
+                        .map_or(Ok(200), |d| d.parse())?,
+                )
+            }
+            "--resume" => options.resume = Some(PathBuf::from(value()?)),
+            "--save" => options.save = Some(PathBuf::from(value()?)),
+            "--tick" => options.tick = Some(value()?.parse()?),
+            "--max-ticks" => options.max_ticks = Some(value()?.parse()?),
+            "--turns" => options.turns = Some(TurnPolicy::parse(value()?)?),
+            "--generate" => {
+                let value = value()?;
+                let (width, height) = value.split_once('x').ok_or(Error::msg(format!(
+                    "Expected <width>x<height>, got {}",
+                    value
+                )))?;
+                options.generate = Some((width.parse()?, height.parse()?));
+            }
+            "--carts" => options.carts = value()?.parse()?,
+            "--seed" => options.seed = value()?.parse()?,
+            "--cart-turns" => {
+                let value = value()?;
+                let (id, turns) = value
+                    .split_once('=')
+                    .ok_or(Error::msg(format!("Expected <id>=<policy>, got {}", value)))?;
+                options
+                    .cart_turns
+                    .push((id.parse()?, TurnPolicy::parse(turns)?));
+            }
+            _ => Err(Error::msg(format!("Unknown argument {}", flag)))?,
+        }
+    }
+    if options.save.is_some() != options.tick.is_some() {
+        Err(Error::msg("--save and --tick must be given together"))?
+    }
+    Ok(options)
+}
+
 fn main() -> Result<(), Error> {
-    let (map, carts) = parse_input(&read_to_string(Path::new("data/input13.txt"))?)?;
-    let (x, y) = solve1(&map, carts.clone());
-    println!("{},{}", x, y);
-    let (x, y) = solve2(&map, carts);
-    println!("{},{}", x, y);
+    let args: Vec<String> = env::args().collect();
+    let options = parse_options(&args)?;
+    if let Some((width, height)) = options.generate {
+        print!("{}", generate(width, height, options.carts, options.seed)?);
+        return Ok(());
+    }
+    let mut start = match options.resume {
+        Some(path) => Snapshot::from_text(&read_to_string(path)?)?,
+        None => {
+            let (map, carts) = parse_input(&read_to_string(Path::new("data/input13.txt"))?)?;
+            Snapshot::new(map, carts)
+        }
+    };
+    validate(&start.map, &start.carts)?;
+    for cart in start.carts.iter_mut() {
+        let overridden = options.cart_turns.iter().rfind(|(id, _)| *id == cart.id);
+        if let Some(turns) = overridden.map(|(_, t)| t).or(options.turns.as_ref()) {
+            cart.turns = turns.for_cart(cart.id);
+        }
+    }
+
+    if let (Some(path), Some(tick)) = (options.save, options.tick) {
+        let mut simulation = Simulation::resume(&start, true);
+        while simulation.tick < tick && simulation.num_carts() > 1 {
+            simulation.run_tick()?;
+        }
+        return Ok(fs::write(path, simulation.snapshot()?.to_text())?);
+    }
+    if let Some(format) = options.events {
+        return print_events(&start, &format, options.max_ticks);
+    }
+    if let Some(delay) = options.animate {
+        return animate(&start, delay);
+    }
+    let outcome = solve1(&start, options.max_ticks)?;
+    println!(
+        "{}",
+        outcome.to_text("No collision ever happens", |crash| {
+            let (x, y) = crash.location;
+            let [moved, hit] = crash.carts;
+            format!(
+                "{},{} (cart {} crashed into cart {} in tick {})",
+                x, y, moved, hit, crash.tick
+            )
+        })
+    );
+    let outcome = solve2(&start, options.max_ticks)?;
+    println!(
+        "{}",
+        outcome.to_text("More than one cart is always left", |survivor| {
+            let (x, y) = survivor.location;
+            format!(
+                "{},{} (cart {} is left after {} ticks)",
+                x, y, survivor.cart, survivor.ticks
+            )
+        })
+    );
     Ok(())
 }
 
-type Map = Vec<Vec<Track>>;
+type Map = Grid<Track>;
+
+/// The map with the carts drawn on it, as in the puzzle input.
+fn draw(map: &Map, carts: &[Cart]) -> String {
+    let mut drawing = Grid::from_fn(map.width(), map.height(), |x, y| map[(x, y)].to_char());
+    for cart in carts {
+        drawing[cart.location] = cart.direction.to_char();
+    }
+    drawing.to_string() + "\n"
+}
+
+/// Tries to add the rectangular loop with the given corners. Its track may only cross other
+/// loops at right angles, and no other track may come near its corners, so that every curve
+/// connects unambiguously.
+fn add_loop(map: &mut Map, (left, top): (usize, usize), (right, bottom): (usize, usize)) -> bool {
+    let corners = [(left, top), (right, top), (left, bottom), (right, bottom)];
+    let is_corner = |location| corners.contains(&location);
+    let mut cells = vec![];
+    for x in left..=right {
+        cells.push(((x, top), Track::Horizontal));
+        cells.push(((x, bottom), Track::Horizontal));
+    }
+    for y in top + 1..bottom {
+        cells.push(((left, y), Track::Vertical));
+        cells.push(((right, y), Track::Vertical));
+    }
+    let around = |(x, y): (usize, usize)| {
+        (x.saturating_sub(1)..=x + 1)
+            .flat_map(move |nx| (y.saturating_sub(1)..=y + 1).map(move |ny| (nx, ny)))
+            .filter_map(|(nx, ny)| map.get(nx, ny).copied())
+    };
+
+    if corners.iter().any(|c| around(*c).any(|t| t != Track::None)) {
+        return false;
+    }
+    for &(location, track) in cells.iter() {
+        if around(location).any(|t| t.curve_options().is_some()) {
+            return false;
+        }
+        match (map[location], track) {
+            (Track::None, _) => {}
+            (Track::Vertical, Track::Horizontal) | (Track::Horizontal, Track::Vertical) => {}
+            _ => return false,
+        }
+    }
+    for (location, track) in cells {
+        map[location] = match map[location] {
+            Track::None if is_corner(location) => {
+                if location == (left, top) || location == (right, bottom) {
+                    Track::CurveForward
+                } else {
+                    Track::CurveBackward
+                }
+            }
+            Track::None => track,
+            _ => Track::Intersection,
+        };
+    }
+    true
+}
+
+/// A random map of loops crossing each other, with carts on straight pieces of track. Carts
+/// keep their distance from curves and from each other, so that the track below them can be
+/// inferred.
+fn generate(width: usize, height: usize, num_carts: usize, seed: u64) -> Result<String, Error> {
+    let mut rng = Rng { state: seed };
+    let mut map = Grid::new(width, height, Track::None);
+    if width >= 3 && height >= 3 {
+        for _ in 0..width * height / 4 {
+            let (x1, x2) = (rng.below(width), rng.below(width));
+            let (y1, y2) = (rng.below(height), rng.below(height));
+            let (left, right) = (x1.min(x2), x1.max(x2));
+            let (top, bottom) = (y1.min(y2), y1.max(y2));
+            if right - left >= 2 && bottom - top >= 2 {
+                add_loop(&mut map, (left, top), (right, bottom));
+            }
+        }
+    }
+
+    let mut candidates = vec![];
+    for y in 0..height {
+        for x in 0..width {
+            if matches!(map[(x, y)], Track::Horizontal | Track::Vertical) {
+                candidates.push((x, y));
+            }
+        }
+    }
+    let mut carts: Vec<Cart> = vec![];
+    while carts.len() < num_carts && !candidates.is_empty() {
+        let location = candidates.swap_remove(rng.below(candidates.len()));
+        let (x, y) = location;
+        let clear = (x.saturating_sub(1)..=x + 1).all(|nx| {
+            (y.saturating_sub(1)..=y + 1).all(|ny| {
+                map.get(nx, ny).is_none_or(|t| t.curve_options().is_none())
+                    && !carts.iter().any(|c| c.location == (nx, ny))
+            })
+        });
+        if !clear {
+            continue;
+        }
+        let directions = match map[location] {
+            Track::Horizontal => [Direction::Left, Direction::Right],
+            _ => [Direction::Up, Direction::Down],
+        };
+        carts.push(Cart {
+            id: carts.len(),
+            location,
+            direction: directions[rng.below(2)],
+            turns: TurnPolicy::default(),
+        });
+    }
+    if carts.len() < num_carts {
+        Err(Error::msg(format!(
+            "Only found room for {} carts",
+            carts.len()
+        )))?
+    }
+    Ok(draw(&map, &carts))
+}
 
 fn parse_carts(input: &str) -> Vec<Cart> {
     fn parse_line(line: &str, line_number: usize) -> Vec<Cart> {
@@ -187,32 +1380,157 @@ fn parse_carts(input: &str) -> Vec<Cart> {
             .enumerate()
             .filter(|(_i, d)| d.is_some())
             .map(|(i, d)| Cart {
-                location: (i as i32, line_number as i32),
+                id: 0,
+                location: (i, line_number),
                 direction: d.unwrap(),
-                next_turn: Turn::Left,
+                turns: TurnPolicy::default(),
             })
             .collect()
     }
 
-    input
+    let mut carts: Vec<_> = input
         .lines()
         .enumerate()
         .flat_map(|(line_number, line)| parse_line(line, line_number))
-        .collect()
+        .collect();
+    for (id, cart) in carts.iter_mut().enumerate() {
+        cart.id = id;
+    }
+    carts
+}
+
+/// A cell of the input: either a visible piece of track, or a cart hiding the track below it.
+#[derive(Clone, Copy)]
+enum Cell {
+    Track(Track),
+    Cart(Direction),
+}
+
+impl Cell {
+    fn from_char(c: char) -> Result<Self, Error> {
+        Ok(match Direction::from_char(c) {
+            Some(direction) => Cell::Cart(direction),
+            None => Cell::Track(Track::from_char(c)?),
+        })
+    }
+}
+
+/// Whether the track in the neighbouring cell in `direction` leads back into `location`:
+/// `Some` when that is certain and `None` when it depends on track hidden below carts.
+fn leads_back(cells: &Grid<Cell>, location: (usize, usize), direction: Direction) -> Option<bool> {
+    let neighbour = direction.step_from(location);
+    let back = direction.opposite();
+    // Whether the cell next to the neighbour in direction `d` leads into it, judging curves
+    // and carts by what they could be.
+    let may_lead_into = |d: Direction| {
+        let (x, y) = d.step_from(neighbour);
+        match cells.get(x, y) {
+            None | Some(Cell::Track(Track::None)) => Some(false),
+            Some(Cell::Cart(_)) => None,
+            Some(Cell::Track(track)) if track.curve_options().is_some() => None,
+            Some(Cell::Track(track)) => Some(track.connects(d.opposite())),
+        }
+    };
+    match cells.get(neighbour.0, neighbour.1) {
+        None => Some(false),
+        Some(Cell::Cart(_)) => None,
+        Some(Cell::Track(track)) => match track.curve_options() {
+            None => Some(track.connects(back)),
+            Some(options) => {
+                // A curve leads back if its other end may connect while the opposite corner
+                // cannot.
+                let [toward, away] = if options[0].contains(&back) {
+                    options
+                } else {
+                    [options[1], options[0]]
+                };
+                let other_end = toward.into_iter().find(|d| *d != back).unwrap();
+                let toward_possible = may_lead_into(other_end) != Some(false);
+                let away_possible = away.iter().all(|d| may_lead_into(*d) != Some(false));
+                match (toward_possible, away_possible) {
+                    (false, _) => Some(false),
+                    (true, false) => Some(true),
+                    (true, true) => None,
+                }
+            }
+        },
+    }
+}
+
+/// Infers the track below a cart: it has to continue in the cart's direction, and connect to
+/// exactly those neighbours that lead into it.
+fn infer_track(
+    cells: &Grid<Cell>,
+    location: (usize, usize),
+    direction: Direction,
+) -> Result<Track, Error> {
+    use Direction::{Down, Left, Right, Up};
+    let candidates: [&[Direction]; 7] = [
+        &[Up, Down],
+        &[Left, Right],
+        &[Down, Right],
+        &[Up, Left],
+        &[Down, Left],
+        &[Up, Right],
+        &[Up, Down, Left, Right],
+    ];
+    let valid: Vec<_> = candidates
+        .into_iter()
+        .filter(|connections| {
+            connections.contains(&direction)
+                && Direction::ALL
+                    .into_iter()
+                    .all(|d| leads_back(cells, location, d) != Some(!connections.contains(&d)))
+        })
+        .collect();
+    let (x, y) = location;
+    match valid[..] {
+        [connections] => Ok(Track::from_connections(connections)),
+        [] => Err(Error::msg(format!(
+            "no track fits below the cart at {},{}",
+            x, y
+        ))),
+        _ => Err(Error::msg(format!(
+            "ambiguous track below the cart at {},{}",
+            x, y
+        ))),
+    }
 }
 
 fn parse_map(input: &str) -> Result<Map, Error> {
-    fn parse_line(line: &str) -> Result<Vec<Track>, Error> {
+    fn parse_line(line: &str) -> Result<Vec<Cell>, Error> {
         line.chars()
-            .map(Track::from_char)
-            .collect::<Result<Vec<Track>, _>>()
+            .map(Cell::from_char)
+            .collect::<Result<Vec<Cell>, _>>()
     }
 
-    input
+    let mut rows = input
         .lines()
         .filter(|l| !l.is_empty())
         .map(parse_line)
-        .collect::<Result<Vec<_>, _>>()
+        .collect::<Result<Vec<_>, _>>()?;
+    let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    for row in rows.iter_mut() {
+        row.resize(width, Cell::Track(Track::None));
+    }
+    let cells = Grid::from_rows(rows)?;
+
+    let mut problems = vec![];
+    let map = Grid::from_fn(cells.width(), cells.height(), |x, y| match cells[(x, y)] {
+        Cell::Track(track) => track,
+        Cell::Cart(direction) => infer_track(&cells, (x, y), direction).unwrap_or_else(|e| {
+            problems.push(e.to_string());
+            Track::None
+        }),
+    });
+    if problems.is_empty() {
+        Ok(map)
+    } else {
+        Err(Error::msg(format!(
+            "Invalid track layout:\n{}",
+            problems.join("\n")
+        )))
+    }
 }
 fn parse_input(input: &str) -> Result<(Map, Vec<Cart>), Error> {
     let map = parse_map(input)?;
@@ -227,23 +1545,341 @@ mod tests {
 
     #[test]
     fn test_parse_map() {
-        let input = " |- \n/\\+ \n<>v^";
-        let expected = [
-            [Track::None, Track::Vertical, Track::Horizontal, Track::None],
-            [
+        let input = " |- \n/\\+ \n    \n/>-\\\n^  v\n\\-</";
+        let expected = vec![
+            vec![Track::None, Track::Vertical, Track::Horizontal, Track::None],
+            vec![
                 Track::CurveForward,
                 Track::CurveBackward,
                 Track::Intersection,
                 Track::None,
             ],
-            [
+            vec![Track::None; 4],
+            vec![
+                Track::CurveForward,
+                Track::Horizontal,
                 Track::Horizontal,
+                Track::CurveBackward,
+            ],
+            vec![Track::Vertical, Track::None, Track::None, Track::Vertical],
+            vec![
+                Track::CurveBackward,
                 Track::Horizontal,
-                Track::Vertical,
-                Track::Vertical,
+                Track::Horizontal,
+                Track::CurveForward,
             ],
         ];
         let parsed = parse_map(input).unwrap();
-        assert_eq!(parsed, expected);
+        assert_eq!(parsed, Grid::from_rows(expected).unwrap());
+    }
+
+    fn validation_error(input: &str) -> String {
+        let (map, carts) = parse_input(input).unwrap();
+        validate(&map, &carts).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_validate() {
+        let (map, carts) = parse_input("/->-\\\n|   |\n\\-+-/\n  |  \n  |  ").unwrap();
+        assert_eq!(
+            validate(&map, &carts).unwrap_err().to_string(),
+            "Invalid track layout:\n\
+             disconnected track end at 2,2 going up\n\
+             disconnected track end at 2,4 going down"
+        );
+        assert!(validate(&map, &carts[..0]).is_err());
+
+        let (map, carts) = parse_input("/->-\\\n|   |\n\\---/").unwrap();
+        assert!(validate(&map, &carts).is_ok());
+
+        let (map, _) = parse_input("/-\\\n| |\n\\-/").unwrap();
+        let carts = [Cart {
+            id: 0,
+            location: (1, 1),
+            direction: Direction::Up,
+            turns: TurnPolicy::default(),
+        }];
+        assert_eq!(
+            validate(&map, &carts).unwrap_err().to_string(),
+            "Invalid track layout:\ncart at 1,1 is not on track going up"
+        );
+        assert!(validation_error("  |  \n -/- \n  |  ").contains("ambiguous curve at 2,1"));
+        assert_eq!(
+            validation_error("/>\\\n\\-/ -"),
+            "Invalid track layout:\n\
+             disconnected track end at 4,1 going left\n\
+             disconnected track end at 4,1 going right"
+        );
+    }
+
+    #[test]
+    fn test_track_below_carts() {
+        let input = ">-\\\n| |\n\\-v-\\\n  | |\n  \\-/";
+        let (map, carts) = parse_input(input).unwrap();
+        assert_eq!(map[(0, 0)], Track::CurveForward);
+        assert_eq!(map[(2, 2)], Track::Intersection);
+        assert!(validate(&map, &carts).is_ok());
+        let Outcome::Found(crash) = solve1(&Snapshot::new(map, carts), None).unwrap() else {
+            panic!("no crash")
+        };
+        assert_eq!(crash.location, (4, 2));
+
+        assert_eq!(
+            parse_map("  |  \n  v  \n->^<-\n  ^  \n  |  ")
+                .unwrap_err()
+                .to_string(),
+            "Invalid track layout:\nambiguous track below the cart at 2,2"
+        );
+        assert_eq!(
+            parse_map("|\n>").unwrap_err().to_string(),
+            "Invalid track layout:\nno track fits below the cart at 0,1"
+        );
+    }
+
+    #[test]
+    fn test_events() {
+        let input = "/>-<\\  \n|   |  \n| /<+-\\\n| | | v\n\\>+</ |\n  |   ^\n  \\<->/";
+        let (map, carts) = parse_input(input).unwrap();
+        let mut simulation = Simulation::new(&map, carts.clone(), true);
+        let mut events = vec![];
+        while simulation.num_carts() > 1 {
+            events.extend(simulation.move_next().unwrap());
+        }
+        assert_eq!(
+            events[..4],
+            [
+                Event::Crash {
+                    tick: 0,
+                    carts: [1, 0],
+                    location: (2, 0)
+                },
+                Event::Removal {
+                    tick: 0,
+                    cart: 1,
+                    location: (2, 0)
+                },
+                Event::Removal {
+                    tick: 0,
+                    cart: 0,
+                    location: (2, 0)
+                },
+                Event::Turn {
+                    tick: 0,
+                    cart: 4,
+                    location: (2, 4),
+                    turn: Turn::Left
+                },
+            ]
+        );
+        assert_eq!(
+            events
+                .iter()
+                .filter(|e| matches!(e, Event::Crash { .. }))
+                .count(),
+            4
+        );
+        assert_eq!(
+            events[0].to_json(),
+            r#"{"tick":0,"event":"crash","carts":[1,0],"x":2,"y":0}"#
+        );
+        assert_eq!(events[1].to_text(), "tick 0: cart 1 removed at 2,0");
+        assert_eq!(events[3].to_text(), "tick 0: cart 4 turned left at 2,4");
+
+        assert_eq!(
+            solve1(&Snapshot::new(map.clone(), carts.clone()), None).unwrap(),
+            Outcome::Found(Crash {
+                tick: 0,
+                carts: [1, 0],
+                location: (2, 0)
+            })
+        );
+        assert_eq!(
+            solve2(&Snapshot::new(map, carts), None).unwrap(),
+            Outcome::Found(Survivor {
+                cart: 8,
+                location: (6, 4),
+                ticks: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let input = "/>-<\\  \n|   |  \n| /<+-\\\n| | | v\n\\>+</ |\n  |   ^\n  \\<->/";
+        let (map, carts) = parse_input(input).unwrap();
+        let mut simulation = Simulation::new(&map, carts, true);
+        simulation.run_tick().unwrap();
+        simulation.run_tick().unwrap();
+        let text = simulation.snapshot().unwrap().to_text();
+        assert_eq!(
+            text,
+            "tick 2\n\
+             cart 2 on | turns L,S,R\n\
+             cart 7 on | turns L,S,R\n\
+             cart 8 on | turns L,S,R\n\
+             \n\
+             /---\\  \n\
+             |   |  \n\
+             | /-+-\\\n\
+             | v | |\n\
+             \\-+-/ |\n\
+             \x20 ^   ^\n\
+             \x20 \\---/\n"
+        );
+
+        let snapshot = Snapshot::from_text(&text).unwrap();
+        assert_eq!(snapshot.map, map);
+        let mut restored = Simulation::resume(&snapshot, true);
+        while simulation.num_carts() > 1 {
+            assert_eq!(
+                restored.move_next().unwrap(),
+                simulation.move_next().unwrap()
+            );
+        }
+        assert!(restored.carts().eq(simulation.carts()));
+
+        let Outcome::Found(crash) = solve1(&snapshot, None).unwrap() else {
+            panic!("no crash")
+        };
+        assert_eq!(crash.tick, 2);
+        assert_eq!(
+            solve2(&snapshot, None).unwrap(),
+            Outcome::Found(Survivor {
+                cart: 8,
+                location: (6, 4),
+                ticks: 3
+            })
+        );
+        assert_eq!(solve2(&snapshot, Some(2)).unwrap(), Outcome::TickLimit(2));
+
+        assert_eq!(
+            Snapshot::from_text("tick 0\ncart 0 on - turns L,S,R\n\n->-<-")
+                .unwrap_err()
+                .to_string(),
+            "The header lists 1 carts, but the map shows 2"
+        );
+    }
+
+    #[test]
+    fn test_turn_policy() {
+        let mut turns = TurnPolicy::parse("L,L,S,R").unwrap();
+        let taken: Vec<_> = (0..6).map(|_| turns.next().to_char()).collect();
+        assert_eq!(taken, ['L', 'L', 'S', 'R', 'L', 'L']);
+        assert_eq!(turns.to_text(), "S,R,L,L");
+
+        let random = TurnPolicy::parse("random:42").unwrap();
+        assert_eq!(random.to_text(), "random:42");
+        let take = |mut turns: TurnPolicy| (0..20).map(|_| turns.next()).collect::<Vec<_>>();
+        assert_eq!(take(random.for_cart(1)), take(random.for_cart(1)));
+        assert_ne!(take(random.for_cart(1)), take(random.for_cart(2)));
+        assert!(TurnPolicy::parse("L,X").is_err());
+        assert!(TurnPolicy::parse("").is_err());
+
+        let (map, carts) = parse_input("/>\\\n| |\n\\-+-\\\n  | |\n  \\-/").unwrap();
+        let location_after_intersection = |turns: &str| {
+            let mut carts = carts.clone();
+            carts[0].turns = TurnPolicy::parse(turns).unwrap();
+            let mut simulation = Simulation::new(&map, carts, false);
+            for _ in 0..4 {
+                simulation.move_next().unwrap();
+            }
+            let location = simulation.carts().next().unwrap().location;
+            location
+        };
+        assert_eq!(location_after_intersection("L,S,R"), (3, 2));
+        assert_eq!(location_after_intersection("S"), (2, 3));
+        assert_eq!(location_after_intersection("R"), (1, 2));
+    }
+
+    #[test]
+    fn test_no_collision() {
+        let (map, carts) = parse_input("/>-\\\n|  |\n\\-</").unwrap();
+        assert!(validate(&map, &carts).is_ok());
+        assert_eq!(
+            solve1(&Snapshot::new(map.clone(), carts.clone()), None).unwrap(),
+            Outcome::Cycle {
+                start: 0,
+                period: 10,
+                carts: 2
+            }
+        );
+        assert_eq!(
+            solve1(&Snapshot::new(map, carts), Some(5)).unwrap(),
+            Outcome::TickLimit(5)
+        );
+
+        let (map, carts) = parse_input("->-<-").unwrap();
+        assert_eq!(
+            solve2(&Snapshot::new(map, carts), None).unwrap(),
+            Outcome::NoCarts
+        );
+
+        let (map, carts) = parse_input("/>-\\\n|  |\n\\-</\n->-<-").unwrap();
+        let outcome = solve2(&Snapshot::new(map, carts), None).unwrap();
+        assert_eq!(
+            outcome.to_text("More than one cart is always left", |_| String::new()),
+            "More than one cart is always left: from tick 1 on, the remaining 2 carts repeat \
+             every 10 ticks"
+        );
+    }
+
+    #[test]
+    fn test_run_to_crash() {
+        let (map, carts) = parse_input("/>-\\\n|  |\n\\-</\n->-<-").unwrap();
+        let mut simulation = Simulation::new(&map, carts.clone(), true);
+        let mut crash_sites = vec![];
+        assert!(matches!(
+            run_to_crash(&mut simulation, &mut crash_sites, || Ok(false)).unwrap(),
+            CrashSearch::Crash
+        ));
+        assert_eq!((crash_sites.len(), simulation.tick), (1, 1));
+        let Ok(CrashSearch::NotFound(message)) =
+            run_to_crash(&mut simulation, &mut crash_sites, || Ok(false))
+        else {
+            panic!("expected no further crash");
+        };
+        assert_eq!(message, "no further crash, the carts repeat every 10 ticks");
+
+        let mut simulation = Simulation::new(&map, carts, true);
+        simulation.run_tick().unwrap();
+        assert!(matches!(
+            run_to_crash(&mut simulation, &mut crash_sites, || Ok(true)).unwrap(),
+            CrashSearch::Quit
+        ));
+        assert_eq!(simulation.tick, 2);
+    }
+
+    #[test]
+    fn test_generate() {
+        for seed in 0..20 {
+            let input = generate(40, 20, 10, seed).unwrap();
+            let (map, carts) = parse_input(&input).unwrap();
+            assert_eq!((map.width(), map.height(), carts.len()), (40, 20, 10));
+            validate(&map, &carts).unwrap();
+            assert!(solve2(&Snapshot::new(map, carts), Some(1000)).is_ok());
+        }
+        assert_eq!(
+            generate(40, 20, 10, 3).unwrap(),
+            generate(40, 20, 10, 3).unwrap()
+        );
+        assert_ne!(
+            generate(40, 20, 10, 3).unwrap(),
+            generate(40, 20, 10, 4).unwrap()
+        );
+        assert_eq!(
+            generate(2, 2, 1, 0).unwrap_err().to_string(),
+            "Only found room for 0 carts"
+        );
+    }
+
+    #[test]
+    fn test_run_off_track() {
+        let (map, carts) = parse_input("->- ").unwrap();
+        assert_eq!(
+            solve1(&Snapshot::new(map, carts), None)
+                .unwrap_err()
+                .to_string(),
+            "Cart ran off the track at 3,0"
+        );
     }
 }
@@ -1,76 +1,280 @@
-#![feature(iter_advance_by)]
-
 use anyhow::Error;
+use aoc2018::recipes::{RecipeBoard, Scoreboard};
 use std::env;
+use std::path::PathBuf;
 
-fn get_input() -> Result<(i32, usize), Error> {
-    let inputs: Vec<_> = env::args().collect();
+const PROGRESS_INTERVAL: usize = 5_000_000;
 
-    Ok((
-        inputs
-            .get(1)
-            .ok_or(Error::msg("Missing input argument"))?
-            .parse()?,
-        inputs[1].len(),
-    ))
+enum Query {
+    Range(usize, usize),
+    ScoreAt(usize),
+    Count(String, usize),
 }
-fn main() -> Result<(), Error> {
-    let (input, n) = get_input()?;
-    let result = solve(input);
-    println!("{:010}", result);
-    println!("{}", solve2(input, n));
-    Ok(())
+
+struct Options {
+    input: Option<String>,
+    board: RecipeBoard,
+    max_recipes: usize,
+    length: usize,
+    queries: Vec<Query>,
+    scoreboard: Option<PathBuf>,
 }
 
-fn scores_iter() -> impl Iterator<Item = usize> {
-    let mut scores = vec![3, 7];
-    let mut pos1 = 0usize;
-    let mut pos2 = 1usize;
-    let mut next_to_return = 0usize;
+/// The puzzle input, which may be left out when only running queries, and optional flags:
+/// * `--scores <score>[,<score>...]`: the recipes on the board at the start, instead of 3,7
+/// * `--elves <recipe>[,<recipe>...]`: the recipes the elves start at, one elf per starting
+///   recipe by default
+/// * `--base <n>`: split combined scores into digits in this base instead of 10
+/// * `--max-recipes <n>`: create at most this many recipes, giving up on answers and queries
+///   that need more, one billion by default
+/// * `--length <n>`: the number of scores to give for part one, instead of 10
+/// * `--range <a>..<b>`, `--score-at <n>`, `--count <digits>:<n>`: print the scores of recipes
+///   `a` up to but not including `b`, the score of recipe `n`, or how often the digits appear
+///   within the first `n` recipes instead of the answers
+/// * `--scoreboard <file>`: continue the recipes saved in the file if it exists, and save them
+///   there afterwards
+fn get_input() -> Result<Options, Error> {
+    let args: Vec<_> = env::args().collect();
+    let list = |value: &str| {
+        value
+            .split(',')
+            .map(|v| v.parse())
+            .collect::<Result<Vec<usize>, _>>()
+    };
 
-    std::iter::from_fn(move || {
-        if scores.len() > next_to_return {
-            next_to_return += 1;
-            return Some(scores[next_to_return - 1]);
+    let mut scores = None;
+    let mut elves = None;
+    let mut base = 10;
+    let mut max_recipes = 1_000_000_000;
+    let mut length = 10;
+    let mut queries = vec![];
+    let mut scoreboard = None;
+    let mut input = None;
+    let mut args = args.iter().skip(1);
+    while let Some(flag) = args.next() {
+        if !flag.starts_with("--") {
+            if input.is_some() {
+                Err(Error::msg(format!("Unexpected argument {}", flag)))?
+            }
+            input = Some(flag.clone());
+            continue;
         }
-
-        let combined = scores[pos1] + scores[pos2];
-        let first = combined / 10;
-        let second = combined % 10;
-        if first > 0 {
-            scores.push(first);
+        let value = args
+            .next()
+            .ok_or(Error::msg(format!("Missing value for {}", flag)))?;
+        match flag.as_str() {
+            "--scores" => scores = Some(list(value)?),
+            "--elves" => elves = Some(list(value)?),
+            "--base" => base = value.parse()?,
+            "--max-recipes" => max_recipes = value.parse()?,
+            "--length" => length = value.parse()?,
+            "--range" => {
+                let (start, end) = value
+                    .split_once("..")
+                    .ok_or(Error::msg(format!("Expected <a>..<b>, got {}", value)))?;
+                let (start, end) = (start.parse()?, end.parse()?);
+                if end < start {
+                    Err(Error::msg(format!("Invalid range {}", value)))?
+                }
+                queries.push(Query::Range(start, end));
+            }
+            "--score-at" => queries.push(Query::ScoreAt(value.parse()?)),
+            "--count" => {
+                let (digits, end) = value
+                    .split_once(':')
+                    .ok_or(Error::msg(format!("Expected <digits>:<n>, got {}", value)))?;
+                queries.push(Query::Count(digits.to_string(), end.parse()?));
+            }
+            "--scoreboard" => scoreboard = Some(PathBuf::from(value)),
+            _ => Err(Error::msg(format!("Unknown argument {}", flag)))?,
         }
-        scores.push(second);
+    }
+    if input.is_none() && queries.is_empty() {
+        Err(Error::msg("Missing input argument"))?
+    }
+    let scores = scores.unwrap_or(RecipeBoard::default().starting_scores().to_vec());
+    let elves = elves.unwrap_or((0..scores.len()).collect());
+    let board = RecipeBoard::new(scores, elves, base)?;
+    Ok(Options {
+        input,
+        board,
+        max_recipes,
+        length,
+        queries,
+        scoreboard,
+    })
+}
 
-        update_pos(&scores, &mut pos1);
-        update_pos(&scores, &mut pos2);
+fn main() -> Result<(), Error> {
+    let options = get_input()?;
+    let board = &options.board;
+    let mut scoreboard = match &options.scoreboard {
+        Some(path) if path.exists() => Scoreboard::load(path, board)?,
+        _ => Scoreboard::new(board),
+    };
 
-        next_to_return += 1;
-        Some(scores[next_to_return - 1])
-    })
+    if let (Some(input), true) = (&options.input, options.queries.is_empty()) {
+        // Targets for part two may be too long to be a number of recipes.
+        let answer = input
+            .parse()
+            .map_err(Error::from)
+            .and_then(|input| solve(&mut scoreboard, input, options.length, options.max_recipes));
+        match answer {
+            Ok(answer) => println!("{}", answer),
+            Err(e) => eprintln!("Skipping part one: {}", e),
+        }
+        let target = board.parse_scores(input)?;
+        if target.is_empty() {
+            Err(Error::msg("Empty input"))?
+        }
+        let answer = solve2(
+            &mut scoreboard,
+            &target,
+            options.max_recipes,
+            PROGRESS_INTERVAL,
+        )?;
+        println!("{}", answer);
+    }
+    for query in options.queries.iter() {
+        let needed = match query {
+            Query::Range(_, end) | Query::Count(_, end) => Some(*end),
+            Query::ScoreAt(index) => index.checked_add(1),
+        };
+        within_limit(needed, options.max_recipes)?;
+        match query {
+            Query::Range(start, end) => println!("{}", scoreboard.digits(*start..*end)),
+            Query::ScoreAt(index) => {
+                println!("{}", board.digit(scoreboard.score(*index)))
+            }
+            Query::Count(digits, end) => {
+                let pattern = board.parse_scores(digits)?;
+                if pattern.is_empty() {
+                    Err(Error::msg("Empty pattern"))?
+                }
+                println!("{}", scoreboard.count(&pattern, *end))
+            }
+        }
+    }
+
+    if let Some(path) = &options.scoreboard {
+        scoreboard.save(path)?;
+    }
+    Ok(())
 }
 
-fn solve(input: i32) -> i64 {
-    scores_iter()
-        .skip(input as usize)
-        .take(10)
-        .fold(0i64, |acc, val| 10 * acc + (val as i64))
+/// Checks that the number of recipes needed, if it fits in a `usize`, is within the limit.
+fn within_limit(needed: Option<usize>, max_recipes: usize) -> Result<usize, Error> {
+    match needed {
+        Some(needed) if needed <= max_recipes => Ok(needed),
+        _ => Err(Error::msg(format!(
+            "More than the limit of {} recipes needed",
+            max_recipes
+        ))),
+    }
 }
 
-fn solve2(input: i32, n: usize) -> usize {
-    let mut value = 0;
-    let mod_value = i32::pow(10, (n - 1) as u32);
+/// The scores of the `length` recipes after the first `input` ones, as digits, if they are
+/// within the first `max_recipes` recipes.
+fn solve(
+    scoreboard: &mut Scoreboard,
+    input: usize,
+    length: usize,
+    max_recipes: usize,
+) -> Result<String, Error> {
+    let end = within_limit(input.checked_add(length), max_recipes)?;
+    Ok(scoreboard.digits(input..end))
+}
 
-    for (i, score) in scores_iter().enumerate() {
-        value %= mod_value;
-        value = value * 10 + (score as i32);
-        if value == input {
-            return i - (n - 1);
+/// The number of recipes before the target sequence of scores first appears, if it does so
+/// within the first `max_recipes` recipes. Reports progress after every `interval` recipes.
+fn solve2(
+    scoreboard: &mut Scoreboard,
+    target: &[usize],
+    max_recipes: usize,
+    interval: usize,
+) -> Result<usize, Error> {
+    let mut start = 0;
+    let mut end = 0;
+    while end < max_recipes {
+        end = (end + interval).min(max_recipes);
+        if let Some(index) = scoreboard.find(target, start, end) {
+            return Ok(index);
+        }
+        // Occurrences that end in the next part may start in this one.
+        start = (end + 1).saturating_sub(target.len());
+        if end < max_recipes {
+            eprintln!("Searched {} million recipes", end / 1_000_000);
         }
     }
-    unreachable!()
+    Err(Error::msg(format!(
+        "Pattern not found within {} recipes",
+        max_recipes
+    )))
 }
 
-fn update_pos(scores: &Vec<usize>, pos: &mut usize) {
-    *pos = (*pos + 1 + scores[*pos]) % scores.len();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_long_target() {
+        let input = "1010124515891677925107108510";
+        assert!(input.parse::<usize>().is_err());
+        let board = RecipeBoard::default();
+        let target = board.parse_scores(input).unwrap();
+        let mut scoreboard = Scoreboard::new(&board);
+        assert_eq!(solve2(&mut scoreboard, &target, 1000, 100).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_default_board() {
+        let board = RecipeBoard::default();
+        let mut scoreboard = Scoreboard::new(&board);
+        assert_eq!(solve(&mut scoreboard, 9, 10, 1000).unwrap(), "5158916779");
+        assert_eq!(
+            solve(&mut scoreboard, 2018, 10, 3000).unwrap(),
+            "5941429882"
+        );
+        let target = board.parse_scores("59414").unwrap();
+        assert_eq!(
+            solve2(&mut scoreboard, &target, 1_000_000, PROGRESS_INTERVAL).unwrap(),
+            2018
+        );
+    }
+
+    #[test]
+    fn test_recipe_limit() {
+        let mut scoreboard = Scoreboard::new(&RecipeBoard::default());
+        assert_eq!(solve(&mut scoreboard, 990, 10, 1000).unwrap(), "1775912265");
+        for input in [991, 1010124515891677925, usize::MAX] {
+            assert_eq!(
+                solve(&mut scoreboard, input, 10, 1000)
+                    .unwrap_err()
+                    .to_string(),
+                "More than the limit of 1000 recipes needed"
+            );
+        }
+        assert!(scoreboard.len() < 1010);
+    }
+
+    #[test]
+    fn test_chunked_search() {
+        let target = [5, 9, 4, 1, 4];
+        // The occurrence at 2018 ends in recipe 2022, so all but the last of these split it.
+        for interval in 2019..=2023 {
+            let mut scoreboard = Scoreboard::new(&RecipeBoard::default());
+            assert_eq!(
+                solve2(&mut scoreboard, &target, 10_000, interval).unwrap(),
+                2018
+            );
+        }
+        let mut scoreboard = Scoreboard::new(&RecipeBoard::default());
+        assert_eq!(
+            solve2(&mut scoreboard, &target, 2022, 1000)
+                .unwrap_err()
+                .to_string(),
+            "Pattern not found within 2022 recipes"
+        );
+        assert_eq!(solve2(&mut scoreboard, &target, 2023, 1000).unwrap(), 2018);
+    }
 }
@@ -0,0 +1,3 @@
+pub mod automaton;
+pub mod grid;
+pub mod recipes;
@@ -0,0 +1,552 @@
+use anyhow::Error;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+/// Storage for the scores on a recipe board. Every score is a single digit, so a full `usize`
+/// per recipe mostly stores zeros.
+pub trait Scores {
+    fn with_capacity(capacity: usize) -> Self;
+    fn push(&mut self, score: usize);
+    fn get(&self, index: usize) -> usize;
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// The number of bytes allocated for the scores.
+    fn heap_size(&self) -> usize;
+    /// The largest base whose digits fit.
+    fn max_base() -> usize;
+}
+
+impl Scores for Vec<usize> {
+    fn with_capacity(capacity: usize) -> Self {
+        Vec::with_capacity(capacity)
+    }
+    fn push(&mut self, score: usize) {
+        Vec::push(self, score)
+    }
+    fn get(&self, index: usize) -> usize {
+        self[index]
+    }
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+    fn heap_size(&self) -> usize {
+        self.capacity() * std::mem::size_of::<usize>()
+    }
+    fn max_base() -> usize {
+        usize::MAX
+    }
+}
+
+impl Scores for Vec<u8> {
+    fn with_capacity(capacity: usize) -> Self {
+        Vec::with_capacity(capacity)
+    }
+    fn push(&mut self, score: usize) {
+        Vec::push(self, score as u8)
+    }
+    fn get(&self, index: usize) -> usize {
+        self[index] as usize
+    }
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+    fn heap_size(&self) -> usize {
+        self.capacity()
+    }
+    fn max_base() -> usize {
+        256
+    }
+}
+
+/// Two scores per byte, the first one in the low half.
+#[derive(Debug, Clone, Default)]
+pub struct Nibbles {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Scores for Nibbles {
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(capacity.div_ceil(2)),
+            len: 0,
+        }
+    }
+    fn push(&mut self, score: usize) {
+        if self.len.is_multiple_of(2) {
+            self.bytes.push(score as u8);
+        } else {
+            *self.bytes.last_mut().unwrap() |= (score as u8) << 4;
+        }
+        self.len += 1;
+    }
+    fn get(&self, index: usize) -> usize {
+        (self.bytes[index / 2] >> (4 * (index % 2)) & 0xf) as usize
+    }
+    fn len(&self) -> usize {
+        self.len
+    }
+    fn heap_size(&self) -> usize {
+        self.bytes.capacity()
+    }
+    fn max_base() -> usize {
+        16
+    }
+}
+
+/// The starting recipes, the recipes the elves start at, and the base in which combined scores
+/// are split into new recipes.
+#[derive(Debug, Clone)]
+pub struct RecipeBoard {
+    scores: Vec<usize>,
+    elves: Vec<usize>,
+    base: usize,
+}
+
+impl Default for RecipeBoard {
+    fn default() -> Self {
+        Self {
+            scores: vec![3, 7],
+            elves: vec![0, 1],
+            base: 10,
+        }
+    }
+}
+
+impl RecipeBoard {
+    pub fn new(scores: Vec<usize>, elves: Vec<usize>, base: usize) -> Result<Self, Error> {
+        if !(2..=36).contains(&base) {
+            Err(Error::msg(format!("Base {} is not between 2 and 36", base)))?
+        }
+        if let Some(score) = scores.iter().find(|s| **s >= base) {
+            Err(Error::msg(format!(
+                "Score {} is not a digit in base {}",
+                score, base
+            )))?
+        }
+        if elves.is_empty() {
+            Err(Error::msg("No elves"))?
+        }
+        if let Some(elf) = elves.iter().find(|e| **e >= scores.len()) {
+            Err(Error::msg(format!("No recipe {} to start at", elf)))?
+        }
+        Ok(Self {
+            scores,
+            elves,
+            base,
+        })
+    }
+
+    pub fn starting_scores(&self) -> &[usize] {
+        &self.scores
+    }
+
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    pub fn digit(&self, score: usize) -> char {
+        char::from_digit(score as u32, self.base as u32).unwrap()
+    }
+
+    pub fn parse_score(&self, digit: char) -> Result<usize, Error> {
+        digit
+            .to_digit(self.base as u32)
+            .map(|d| d as usize)
+            .ok_or_else(|| Error::msg(format!("'{}' is not a digit in base {}", digit, self.base)))
+    }
+
+    pub fn parse_scores(&self, digits: &str) -> Result<Vec<usize>, Error> {
+        digits.chars().map(|c| self.parse_score(c)).collect()
+    }
+
+    /// The scores of all recipes, stored a byte each.
+    pub fn scores_iter(&self) -> Recipes<Vec<u8>> {
+        // Digits up to base 36 always fit in a byte.
+        self.recipes_in(Vec::new())
+    }
+
+    /// The scores of all recipes, kept in the given storage while they are created. Reserving
+    /// capacity for the number of recipes needed avoids reallocating. Fails if the storage
+    /// cannot hold digits in the base of the board.
+    pub fn scores_iter_in<S: Scores>(&self, scores: S) -> Result<Recipes<S>, Error> {
+        if self.base > S::max_base() {
+            Err(Error::msg(format!(
+                "The storage only holds digits up to base {}, not base {}",
+                S::max_base(),
+                self.base
+            )))?
+        }
+        Ok(self.recipes_in(scores))
+    }
+
+    fn recipes_in<S: Scores>(&self, mut scores: S) -> Recipes<S> {
+        for score in self.scores.iter() {
+            scores.push(*score);
+        }
+        Recipes {
+            scores,
+            elves: self.elves.clone(),
+            base: self.base,
+            next_to_return: 0,
+        }
+    }
+}
+
+/// Iterates over the scores of the recipes, creating new ones as needed.
+#[derive(Debug, Clone)]
+pub struct Recipes<S> {
+    scores: S,
+    elves: Vec<usize>,
+    base: usize,
+    next_to_return: usize,
+}
+
+impl<S: Scores> Recipes<S> {
+    /// The recipes created so far.
+    pub fn scores(&self) -> &S {
+        &self.scores
+    }
+
+    fn create_recipes(&mut self) {
+        let mut combined: usize = self.elves.iter().map(|e| self.scores.get(*e)).sum();
+        let mut digits = vec![];
+        loop {
+            digits.push(combined % self.base);
+            combined /= self.base;
+            if combined == 0 {
+                break;
+            }
+        }
+        for digit in digits.into_iter().rev() {
+            self.scores.push(digit);
+        }
+
+        for elf in self.elves.iter_mut() {
+            *elf = (*elf + 1 + self.scores.get(*elf)) % self.scores.len();
+        }
+    }
+}
+
+impl<S: Scores> Iterator for Recipes<S> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.scores.len() <= self.next_to_return {
+            self.create_recipes();
+        }
+        self.next_to_return += 1;
+        Some(self.scores.get(self.next_to_return - 1))
+    }
+}
+
+/// Finds a pattern in a stream of values with the Knuth-Morris-Pratt algorithm.
+pub struct Matcher {
+    pattern: Vec<usize>,
+    /// For each prefix of the pattern, the length of its longest proper prefix that is also a
+    /// suffix of it.
+    fallback: Vec<usize>,
+    matched: usize,
+}
+
+impl Matcher {
+    pub fn new(pattern: Vec<usize>) -> Self {
+        let mut fallback = vec![0; pattern.len() + 1];
+        let mut length = 0;
+        for i in 1..pattern.len() {
+            while length > 0 && pattern[i] != pattern[length] {
+                length = fallback[length];
+            }
+            if pattern[i] == pattern[length] {
+                length += 1;
+            }
+            fallback[i + 1] = length;
+        }
+        Self {
+            pattern,
+            fallback,
+            matched: 0,
+        }
+    }
+
+    /// Takes the next value, returning whether the pattern ends with it.
+    pub fn push(&mut self, value: usize) -> bool {
+        if self.matched == self.pattern.len() {
+            self.matched = self.fallback[self.matched];
+        }
+        while self.matched > 0 && self.pattern[self.matched] != value {
+            self.matched = self.fallback[self.matched];
+        }
+        if self.pattern[self.matched] == value {
+            self.matched += 1;
+        }
+        self.matched == self.pattern.len()
+    }
+}
+
+/// The recipes created so far, extended as queries need more of them.
+#[derive(Debug, Clone)]
+pub struct Scoreboard {
+    board: RecipeBoard,
+    recipes: Recipes<Vec<u8>>,
+}
+
+impl Scoreboard {
+    pub fn new(board: &RecipeBoard) -> Self {
+        Self {
+            board: board.clone(),
+            recipes: board.scores_iter(),
+        }
+    }
+
+    /// The number of recipes created so far.
+    pub fn len(&self) -> usize {
+        self.recipes.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Creates recipes until there are at least `len` of them, reserving room for them first
+    /// when possible.
+    pub fn extend_to(&mut self, len: usize) {
+        // Each step adds at most as many recipes as there are elves. Reserving is only an
+        // optimisation, so the recipes are still created if it fails.
+        let needed = len.saturating_sub(self.recipes.scores.len());
+        if needed > 0 {
+            let _ = self
+                .recipes
+                .scores
+                .try_reserve(needed.saturating_add(self.recipes.elves.len()));
+        }
+        while self.recipes.scores.len() < len {
+            self.recipes.create_recipes();
+        }
+    }
+
+    pub fn score(&mut self, index: usize) -> usize {
+        self.extend_to(index + 1);
+        self.recipes.scores[index] as usize
+    }
+
+    /// The scores of the recipes in the range, as digits.
+    pub fn digits(&mut self, range: Range<usize>) -> String {
+        self.extend_to(range.end);
+        range
+            .map(|i| self.board.digit(self.recipes.scores[i] as usize))
+            .collect()
+    }
+
+    /// The first occurrence of the pattern that starts at or after `start` and lies within the
+    /// first `end` recipes.
+    pub fn find(&mut self, pattern: &[usize], start: usize, end: usize) -> Option<usize> {
+        self.extend_to(end);
+        let mut matcher = Matcher::new(pattern.to_vec());
+        (start..end)
+            .find(|i| matcher.push(self.recipes.scores[*i] as usize))
+            .map(|i| i + 1 - pattern.len())
+    }
+
+    /// The number of possibly overlapping occurrences of the pattern within the first `end`
+    /// recipes.
+    pub fn count(&mut self, pattern: &[usize], end: usize) -> usize {
+        self.extend_to(end);
+        let mut matcher = Matcher::new(pattern.to_vec());
+        (0..end)
+            .filter(|i| matcher.push(self.recipes.scores[*i] as usize))
+            .count()
+    }
+
+    /// Writes the board and the positions of the elves on the first line, and the scores as
+    /// digits on the second.
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let list = |values: &[usize]| {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let mut contents = format!(
+            "base {} scores {} elves {} at {}\n",
+            self.board.base,
+            list(&self.board.scores),
+            list(&self.board.elves),
+            list(&self.recipes.elves)
+        );
+        contents.extend(
+            self.recipes
+                .scores
+                .iter()
+                .map(|s| self.board.digit(*s as usize)),
+        );
+        contents.push('\n');
+        Ok(fs::write(path, contents)?)
+    }
+
+    /// Continues a scoreboard saved for the same board.
+    pub fn load(path: &Path, board: &RecipeBoard) -> Result<Self, Error> {
+        let contents = fs::read_to_string(path)?;
+        let mut lines = contents.lines();
+        let header = lines.next().unwrap_or("");
+        let expected = || {
+            Error::msg("Expected `base <n> scores <score>,... elves <recipe>,... at <recipe>,...`")
+        };
+        let (base, rest) = header
+            .strip_prefix("base ")
+            .and_then(|h| h.split_once(" scores "))
+            .ok_or_else(expected)?;
+        let (starting_scores, rest) = rest.split_once(" elves ").ok_or_else(expected)?;
+        let (starting_elves, elves) = rest.split_once(" at ").ok_or_else(expected)?;
+        let list = |values: &str| {
+            values
+                .split(',')
+                .map(|v| v.parse())
+                .collect::<Result<Vec<usize>, _>>()
+        };
+        let different = || Error::msg("The saved scoreboard is for a different board");
+        if base.parse::<usize>()? != board.base
+            || list(starting_scores)? != board.scores
+            || list(starting_elves)? != board.elves
+        {
+            Err(different())?
+        }
+        let elves = list(elves)?;
+        if elves.len() != board.elves.len() {
+            Err(Error::msg(format!(
+                "Expected {} elves, got {}",
+                board.elves.len(),
+                elves.len()
+            )))?
+        }
+        // Parsed straight into bytes, as the saved boards can be huge.
+        let digits = lines.next().unwrap_or("");
+        let mut scores = Vec::with_capacity(digits.len());
+        for digit in digits.chars() {
+            scores.push(board.parse_score(digit)? as u8);
+        }
+        let starting_scores: Vec<_> = board.scores.iter().map(|s| *s as u8).collect();
+        if !scores.starts_with(&starting_scores) {
+            Err(different())?
+        }
+        if let Some(elf) = elves.iter().find(|e| **e >= scores.len()) {
+            Err(Error::msg(format!("No recipe {} for an elf", elf)))?
+        }
+
+        let mut scoreboard = Self::new(board);
+        scoreboard.recipes.scores = scores;
+        scoreboard.recipes.elves = elves;
+        Ok(scoreboard)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_scores<S: Scores>(board: &RecipeBoard, storage: S) -> String {
+        board
+            .scores_iter_in(storage)
+            .unwrap()
+            .take(20)
+            .map(|s| board.digit(s))
+            .collect()
+    }
+
+    #[test]
+    fn test_storage() {
+        let board = RecipeBoard::default();
+        let expected = "37101012451589167792";
+        assert_eq!(first_scores(&board, Vec::<usize>::new()), expected);
+        assert_eq!(first_scores(&board, Vec::<u8>::new()), expected);
+        assert_eq!(first_scores(&board, Nibbles::default()), expected);
+
+        let mut nibbles = Nibbles::with_capacity(5);
+        for score in [1, 15, 0, 9, 7] {
+            nibbles.push(score);
+        }
+        assert_eq!((nibbles.len(), nibbles.heap_size()), (5, 3));
+        assert_eq!(
+            (0..5).map(|i| nibbles.get(i)).collect::<Vec<_>>(),
+            [1, 15, 0, 9, 7]
+        );
+
+        let board = RecipeBoard::new(vec![3, 7], vec![0, 1], 17).unwrap();
+        assert_eq!(
+            board
+                .scores_iter_in(Nibbles::default())
+                .unwrap_err()
+                .to_string(),
+            "The storage only holds digits up to base 16, not base 17"
+        );
+        assert!(board.scores_iter_in(Vec::<u8>::new()).is_ok());
+    }
+
+    #[test]
+    fn test_board() {
+        let board = RecipeBoard::new(vec![9, 9, 9], vec![0, 1, 2], 10).unwrap();
+        assert_eq!(first_scores(&board, Vec::<u8>::new())[..7], *"9992727");
+        let board = RecipeBoard::new(vec![1, 0], vec![0, 1], 2).unwrap();
+        assert_eq!(first_scores(&board, Vec::<u8>::new())[..4], *"1011");
+        assert!(RecipeBoard::new(vec![3, 7], vec![2], 10).is_err());
+        assert!(RecipeBoard::new(vec![3, 17], vec![0], 10).is_err());
+        assert_eq!(
+            RecipeBoard::default()
+                .parse_scores("0a")
+                .unwrap_err()
+                .to_string(),
+            "'a' is not a digit in base 10"
+        );
+    }
+
+    #[test]
+    fn test_matcher() {
+        let mut matcher = Matcher::new(vec![1, 0, 1, 1]);
+        let ends: Vec<_> = [1, 0, 1, 0, 1, 1, 0, 1, 1, 1]
+            .into_iter()
+            .enumerate()
+            .filter(|(_, v)| matcher.push(*v))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(ends, [5, 8]);
+    }
+
+    #[test]
+    fn test_scoreboard() {
+        let board = RecipeBoard::default();
+        let mut scoreboard = Scoreboard::new(&board);
+        scoreboard.extend_to(1000);
+        assert!((1000..1010).contains(&scoreboard.recipes.scores().heap_size()));
+        assert_eq!(scoreboard.digits(9..19), "5158916779");
+        assert_eq!(scoreboard.score(5), 0);
+        assert_eq!(scoreboard.find(&[5, 9, 4, 1, 4], 0, 3000), Some(2018));
+        assert_eq!(scoreboard.find(&[5, 9, 4, 1, 4], 0, 2022), None);
+        assert_eq!(scoreboard.find(&[5, 9, 4, 1, 4], 2019, 3000), None);
+        assert_eq!(scoreboard.find(&[0, 1], 0, 100), Some(3));
+        assert_eq!(scoreboard.find(&[0, 1], 4, 100), Some(5));
+        assert_eq!(scoreboard.count(&[0, 1], 20), 2);
+        assert_eq!(scoreboard.count(&[1], 6), 2);
+
+        let path = std::env::temp_dir().join(format!("scoreboard-{}.txt", std::process::id()));
+        scoreboard.save(&path).unwrap();
+        let mut loaded = Scoreboard::load(&path, &board).unwrap();
+        assert_eq!(loaded.len(), scoreboard.len());
+        assert_eq!(loaded.recipes.scores().heap_size(), loaded.len());
+        assert_eq!(loaded.digits(2000..4000), scoreboard.digits(2000..4000));
+        for other in [
+            RecipeBoard::new(vec![3, 7], vec![0, 1], 8).unwrap(),
+            RecipeBoard::new(vec![3, 7], vec![1, 1], 10).unwrap(),
+            RecipeBoard::new(vec![3, 7, 1], vec![0, 1], 10).unwrap(),
+        ] {
+            assert_eq!(
+                Scoreboard::load(&path, &other).unwrap_err().to_string(),
+                "The saved scoreboard is for a different board"
+            );
+        }
+        fs::remove_file(path).unwrap();
+    }
+}